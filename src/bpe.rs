@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+
+const MIN_COUNT: usize = 4;
+const PAIRS_PER_PASS: usize = 256;
+
+/// a grammar rule: `symbol` expands to `left` followed by `right`
+pub type Rule = (u16, u16, u16);
+
+/// symbol of the k-th rule: unused byte values first, then the extended u16 range
+fn rule_symbol(k: usize, unused: &[u8]) -> u16 {
+    match unused.get(k) {
+        Some(&byte) => byte as u16,
+        None => (256 + k - unused.len()) as u16
+    }
+}
+
+/// byte-pair / Re-Pair style grammar compression. pairs of adjacent symbols are repeatedly replaced by
+/// new symbols, taken from `unused` (bytes not occurring in the input) and then from 256 upwards.
+/// each pass replaces up to `PAIRS_PER_PASS` of the most frequent pairs that share no symbols, until
+/// `max_rules` rules exist or no pair occurs `MIN_COUNT` times.
+pub fn encode(input: &[u8], unused: &[u8], max_rules: usize) -> (Vec<u16>, Vec<Rule>) {
+    let max_rules = max_rules.min(unused.len() + u16::MAX as usize - 255);
+    let mut seq: Vec<u16> = input.iter().map(|&c| c as u16).collect();
+    let mut rules: Vec<Rule> = Vec::new();
+
+    while rules.len() < max_rules {
+        let mut counts: HashMap<(u16, u16), usize> = HashMap::new();
+        for pair in seq.windows(2) {
+            *counts.entry((pair[0], pair[1])).or_default() += 1;
+        }
+        let mut candidates: Vec<((u16, u16), usize)> = counts.into_iter().filter(|&(_, count)| count >= MIN_COUNT).collect();
+        candidates.sort_by_key(|&(pair, count)| (std::cmp::Reverse(count), pair));
+
+        let mut chosen: HashMap<(u16, u16), u16> = HashMap::new();
+        let mut taken: Vec<u16> = Vec::new();
+        for (pair, _) in candidates {
+            if chosen.len() == PAIRS_PER_PASS || rules.len() == max_rules { break }
+            if taken.contains(&pair.0) || taken.contains(&pair.1) { continue }
+            taken.extend([pair.0, pair.1]);
+            let symbol = rule_symbol(rules.len(), unused);
+            rules.push((symbol, pair.0, pair.1));
+            chosen.insert(pair, symbol);
+        }
+        if chosen.is_empty() { break }
+
+        let mut replaced = Vec::with_capacity(seq.len());
+        let mut i = 0;
+        while i < seq.len() {
+            match seq.get(i+1).and_then(|&next| chosen.get(&(seq[i], next))) {
+                Some(&symbol) => { replaced.push(symbol); i += 2; }
+                None => { replaced.push(seq[i]); i += 1; }
+            }
+        }
+        seq = replaced;
+    }
+    (seq, rules)
+}
+
+pub fn decode(seq: &[u16], rules: &[Rule]) -> Vec<u8> {
+    let mut expansions: HashMap<u16, Vec<u8>> = HashMap::new();
+    for &(symbol, left, right) in rules {
+        let mut expansion = expansions.get(&left).cloned().unwrap_or_else(|| vec![left as u8]);
+        expansion.extend( expansions.get(&right).cloned().unwrap_or_else(|| vec![right as u8]) );
+        expansions.insert(symbol, expansion);
+    }
+
+    let mut out = Vec::with_capacity(2*seq.len());
+    for symbol in seq {
+        match expansions.get(symbol) {
+            Some(expansion) => out.extend(expansion),
+            None => out.push(*symbol as u8)
+        }
+    }
+    out
+}
+
+pub fn rules_to_bytes(rules: &[Rule]) -> Vec<u8> {
+    rules.iter().flat_map(|&(symbol, left, right)| [symbol, left, right]).flat_map(|x| x.to_le_bytes()).collect()
+}
+
+pub fn rules_from_bytes(bytes: &[u8]) -> Vec<Rule> {
+    bytes.chunks_exact(6).map(|b| (
+        u16::from_le_bytes([b[0], b[1]]), u16::from_le_bytes([b[2], b[3]]), u16::from_le_bytes([b[4], b[5]])
+    )).collect()
+}
+
+#[test]
+fn encode_decode() {
+    let input = b"<page><title>one</title></page><page><title>two</title></page><page><title>three</title></page><page>".to_vec();
+    let unused = [1u8, 2];
+    let (seq, rules) = encode(&input, &unused, 1000);
+    assert_eq!(rules[0].0, 1);
+    assert!(rules.iter().any(|rule| rule.0 >= 256));
+    assert!(seq.len() < input.len()/2);
+    assert_eq!(rules_from_bytes(&rules_to_bytes(&rules)), rules);
+    assert_eq!(decode(&seq, &rules), input);
+}
@@ -0,0 +1,204 @@
+/// default block size, as for bzip2 -9
+pub const BLOCK_SIZE: usize = 900_000;
+
+/// suffix array by prefix doubling, O(n log^2 n). shorter suffixes sort before longer ones with
+/// the same prefix, as if the input was terminated by a sentinel smaller than all symbols.
+fn suffix_array(input: &[u8]) -> Vec<u32> {
+    let n = input.len();
+    assert!(n < u32::MAX as usize, "suffixes are stored as u32");
+    let mut sa: Vec<u32> = (0..n as u32).collect();
+    if n < 2 { return sa }
+    let mut rank: Vec<u32> = input.iter().map(|&c| c as u32).collect();
+    let mut next_rank = vec![0u32; n];
+
+    let mut k = 1;
+    loop {
+        let key = |i: u32| {
+            let i = i as usize;
+            (rank[i] as u64) << 32 | if i+k < n { rank[i+k] as u64 + 1 } else { 0 }
+        };
+        sa.sort_unstable_by_key(|&i| key(i));
+
+        next_rank[sa[0] as usize] = 0;
+        for j in 1..n {
+            next_rank[sa[j] as usize] = next_rank[sa[j-1] as usize] + (key(sa[j-1]) < key(sa[j])) as u32;
+        }
+        std::mem::swap(&mut rank, &mut next_rank);
+        if rank[sa[n-1] as usize] as usize == n-1 { break }
+        k *= 2;
+    }
+    sa
+}
+
+/// Burrows-Wheeler transform of `input` terminated by a virtual sentinel.
+/// returns the last column without the sentinel and the row at which the sentinel was removed.
+pub fn bwt(input: &[u8]) -> (Vec<u8>, usize) {
+    let n = input.len();
+    if n == 0 { return (Vec::new(), 0) }
+    let sa = suffix_array(input);
+
+    // row 0 is the sentinel suffix, preceded by the last symbol of the input
+    let mut out = Vec::with_capacity(n);
+    out.push(input[n-1]);
+    let mut primary = 0;
+    for (j, &x) in sa.iter().enumerate() {
+        if x == 0 {
+            primary = j+1;
+        } else {
+            out.push(input[x as usize - 1]);
+        }
+    }
+    (out, primary)
+}
+
+/// inverse of `bwt` by LF-mapping
+pub fn unbwt(last: &[u8], primary: usize) -> Vec<u8> {
+    let n = last.len();
+    let row = |i: usize| if i < primary { Some(last[i]) } else if i == primary { None } else { Some(last[i-1]) };
+
+    let mut counts = [0usize; 256];
+    for &c in last { counts[c as usize] += 1; }
+    let mut starts = [0usize; 256];
+    let mut sum = 1; // the sentinel sorts first
+    for c in 0..256 {
+        starts[c] = sum;
+        sum += counts[c];
+    }
+
+    let mut lf = vec![0u32; n+1];
+    let mut seen = [0usize; 256];
+    for (i, lfi) in lf.iter_mut().enumerate() {
+        if let Some(c) = row(i) {
+            *lfi = (starts[c as usize] + seen[c as usize]) as u32;
+            seen[c as usize] += 1;
+        }
+    }
+
+    let mut out = vec![0u8; n];
+    let mut i = 0;
+    for k in (0..n).rev() {
+        out[k] = row(i).unwrap();
+        i = lf[i] as usize;
+    }
+    out
+}
+
+pub fn mtf_encode(input: &[u8]) -> Vec<u8> {
+    let mut order: Vec<u8> = (0..=255u8).collect();
+    input.iter().map(|&c| {
+        let pos = order.iter().position(|&x| x == c).unwrap();
+        order.remove(pos);
+        order.insert(0, c);
+        pos as u8
+    }).collect()
+}
+
+pub fn mtf_decode(input: &[u8]) -> Vec<u8> {
+    let mut order: Vec<u8> = (0..=255u8).collect();
+    input.iter().map(|&pos| {
+        let c = order.remove(pos as usize);
+        order.insert(0, c);
+        c
+    }).collect()
+}
+
+const RUNA: u16 = 0;
+const RUNB: u16 = 1;
+
+/// bzip2-style zero-run coding: runs of zeros are written as bijective base-2 numbers with the digits
+/// RUNA (1) and RUNB (2), least significant first. all other symbols are shifted up by one.
+pub fn zrle_encode(input: &[u8]) -> Vec<u16> {
+    let mut out = Vec::with_capacity(input.len());
+    let mut run = 0usize;
+    let flush = |out: &mut Vec<u16>, mut run: usize| {
+        while run > 0 {
+            if run & 1 == 1 {
+                out.push(RUNA);
+                run = (run-1)/2;
+            } else {
+                out.push(RUNB);
+                run = (run-2)/2;
+            }
+        }
+    };
+    for &c in input {
+        if c == 0 {
+            run += 1;
+        } else {
+            flush(&mut out, run);
+            run = 0;
+            out.push(c as u16 + 1);
+        }
+    }
+    flush(&mut out, run);
+    out
+}
+
+pub fn zrle_decode(input: &[u16]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len());
+    let mut run = 0usize;
+    let mut weight = 1usize;
+    for &c in input {
+        match c {
+            RUNA => { run += weight; weight *= 2; }
+            RUNB => { run += 2*weight; weight *= 2; }
+            _ => {
+                out.resize(out.len()+run, 0);
+                (run, weight) = (0, 1);
+                out.push((c-1) as u8);
+            }
+        }
+    }
+    out.resize(out.len()+run, 0);
+    out
+}
+
+fn push_u32(out: &mut Vec<u16>, x: usize) {
+    out.extend([x as u16, (x >> 16) as u16]);
+}
+
+fn read_u32(codes: &[u16], pos: usize) -> usize {
+    codes[pos] as usize + ((codes[pos+1] as usize) << 16)
+}
+
+/// BWT, MTF and zero-run coding of every block of `block_size` bytes, each block on its own.
+/// per block, two u16 (low word first) hold the primary index and two more the number of codes that follow.
+pub fn encode(input: &[u8], block_size: usize) -> Vec<u16> {
+    let mut out = Vec::new();
+    for block in input.chunks(block_size) {
+        let (last, primary) = bwt(block);
+        let codes = zrle_encode(&mtf_encode(&last));
+        push_u32(&mut out, primary);
+        push_u32(&mut out, codes.len());
+        out.extend(codes);
+    }
+    out
+}
+
+pub fn decode(codes: &[u16]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut pos = 0;
+    while pos < codes.len() {
+        let (primary, len) = (read_u32(codes, pos), read_u32(codes, pos+2));
+        pos += 4;
+        let last = mtf_decode(&zrle_decode(&codes[pos..pos+len]));
+        out.extend(unbwt(&last, primary));
+        pos += len;
+    }
+    out
+}
+
+#[test]
+fn bwt_mtf_zrle() {
+    let (last, primary) = bwt(b"banana");
+    assert_eq!((&last[..], primary), (&b"annbaa"[..], 4));
+
+    let input = b"This is a simple text for encoding this and that information, with rrrrrruns of zzzzzzzzzzzzzzeros.".to_vec();
+    let mtf = mtf_encode(&input);
+    assert_eq!(zrle_decode(&zrle_encode(&mtf)), mtf);
+    assert_eq!(mtf_decode(&mtf), input);
+    for block_size in [1, 10, BLOCK_SIZE] {
+        assert_eq!(decode(&encode(&input, block_size)), input);
+    }
+    assert_eq!(decode(&encode(b"", BLOCK_SIZE)), b"");
+}
@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use crate::prep::{push_escaped, read_controls, write_controls};
+
+const TEXT_TAG: &[u8] = b"<text xml:space=\"preserve\">";
+
+/// number of control symbols `encode` needs: escape and reference marker
+pub const CONTROL_COUNT: usize = 2;
+
+/// bytes that must not be control symbols: bodies end at the first '<'
+pub const EXCLUDED: &[u8] = b"<";
+
+/// length of the beginnings and ends by which earlier bodies are found
+const KEY_LEN: usize = 32;
+
+/// a reference is only written if it shares this many bytes more than its header takes
+const MIN_GAIN: usize = 4;
+
+fn is_redirect(body: &[u8]) -> bool {
+    body.len() >= 9 && body[..9].eq_ignore_ascii_case(b"#REDIRECT")
+}
+
+/// lengths of the common prefix and the common suffix (not overlapping the prefix) of `body` and `reference`
+fn shared(body: &[u8], reference: &[u8]) -> (usize, usize) {
+    let prefix = body.iter().zip(reference).take_while(|(a, b)| a == b).count();
+    let suffix = body[prefix..].iter().rev().zip(reference[prefix..].iter().rev()).take_while(|(a, b)| a == b).count();
+    (prefix, suffix)
+}
+
+fn push_unescaped(out: &mut Vec<u8>, bytes: &[u8], escape: u8) {
+    let mut bytes = bytes.iter();
+    while let Some(&ch) = bytes.next() {
+        out.push(if ch == escape { *bytes.next().unwrap() } else { ch });
+    }
+}
+
+/// replaces page bodies (the content of `<text xml:space="preserve">`) that largely equal an earlier body by
+/// `control_chars[1]`, the header `"{bodies back} {prefix} {suffix};"` and the differing middle. the earlier
+/// body is an exact duplicate, one with the same beginning or end, or the last redirect for redirects.
+/// the output stays XML-like, so that `prepare` can follow.
+pub fn encode(input: &[u8], control_chars: &[u8]) -> Vec<u8> {
+    assert!(!control_chars.iter().any(|ch| EXCLUDED.contains(ch)), "'<' ends bodies and cannot be a control symbol");
+    let marker = control_chars[1];
+
+    let mut out: Vec<u8> = Vec::with_capacity(input.len());
+    write_controls(&mut out, control_chars);
+
+    let mut bodies: Vec<&[u8]> = Vec::new();
+    let mut exact: HashMap<&[u8], usize> = HashMap::new();
+    let mut heads: HashMap<&[u8], usize> = HashMap::new();
+    let mut tails: HashMap<&[u8], usize> = HashMap::new();
+    let mut last_redirect = None;
+
+    let mut n = 0;
+    while n < input.len() {
+        if !input[n..].starts_with(TEXT_TAG) {
+            out.push(input[n]);
+            n += 1;
+            continue
+        }
+        out.extend(TEXT_TAG);
+        n += TEXT_TAG.len();
+        let len = input[n..].iter().position(|&c| c == b'<').unwrap_or(input.len()-n);
+        let body = &input[n..n+len];
+
+        let keyed = (len >= KEY_LEN).then(|| (heads.get(&body[..KEY_LEN]), tails.get(&body[len-KEY_LEN..])));
+        let candidates = [exact.get(body).copied(), keyed.and_then(|k| k.0.copied()), keyed.and_then(|k| k.1.copied()), last_redirect.filter(|_| is_redirect(body))];
+        let best = candidates.into_iter().flatten()
+            .map(|k| (k, shared(body, bodies[k])))
+            .max_by_key(|&(k, (prefix, suffix))| (prefix+suffix, k));
+        match best.map(|(k, (prefix, suffix))| (format!("{} {prefix} {suffix};", bodies.len()-k), prefix, suffix)) {
+            Some((header, prefix, suffix)) if prefix+suffix > header.len() + MIN_GAIN => {
+                out.push(marker);
+                out.extend(header.as_bytes());
+                push_escaped(&mut out, &body[prefix..len-suffix], control_chars);
+            }
+            _ => push_escaped(&mut out, body, control_chars)
+        }
+
+        let k = bodies.len();
+        bodies.push(body);
+        exact.insert(body, k);
+        if len >= KEY_LEN {
+            heads.insert(&body[..KEY_LEN], k);
+            tails.insert(&body[len-KEY_LEN..], k);
+        }
+        if is_redirect(body) { last_redirect = Some(k); }
+        n += len;
+    }
+    out
+}
+
+pub fn decode(input: &[u8]) -> Vec<u8> {
+    let (control_chars, mut n) = read_controls(input);
+    let escape = control_chars[0];
+    let marker = control_chars[1];
+
+    let mut out: Vec<u8> = Vec::with_capacity(2*input.len());
+    let mut bodies: Vec<Range<usize>> = Vec::new(); // positions in `out`
+    while n < input.len() {
+        if !input[n..].starts_with(TEXT_TAG) {
+            out.push(input[n]);
+            n += 1;
+            continue
+        }
+        out.extend(TEXT_TAG);
+        n += TEXT_TAG.len();
+        let end = n + input[n..].iter().position(|&c| c == b'<').unwrap_or(input.len()-n);
+        let start = out.len();
+        if input.get(n) == Some(&marker) {
+            let header_len = input[n..].iter().position(|&c| c == b';').unwrap();
+            let header = std::str::from_utf8(&input[n+1..n+header_len]).unwrap();
+            let [back, prefix, suffix] = header.split(' ').map(|x| x.parse::<usize>().unwrap()).collect::<Vec<_>>()[..] else { panic!("malformed reference {header}") };
+            let reference = bodies[bodies.len()-back].clone();
+            out.extend_from_within(reference.start..reference.start+prefix);
+            push_unescaped(&mut out, &input[n+header_len+1..end], escape);
+            out.extend_from_within(reference.end-suffix..reference.end);
+        } else {
+            push_unescaped(&mut out, &input[n..end], escape);
+        }
+        bodies.push(start..out.len());
+        n = end;
+    }
+    out
+}
+
+#[test]
+fn encode_decode() {
+    let article = crate::fixtures::ARTICLE;
+    let page = |body: &str| crate::fixtures::page("x", 1, body);
+    let input = [
+        page("#REDIRECT [[Albania]]"), page(article), page("#REDIRECT [[Afghanistan]]"), page(""),
+        page(&article.replace("political", "\x01\x02")), page(article), page("#redirect [[Algeria]] {{R from CamelCase}}"),
+        "<text xml:space=\"preserve\">#REDIRECT [[A".to_string()
+    ].concat();
+    let control_chars = [1u8, 2];
+    let encoded = encode(input.as_bytes(), &control_chars);
+    let text = String::from_utf8_lossy(&encoded);
+    assert!(text.contains("\x022 13 2;fghanistan<"));
+    assert!(text.contains(&format!("\x023 21 {};\x01\x01\x01\x02<", article.len()-30)));
+    assert!(text.contains(&format!("\x024 {} 0;<", article.len())));
+    assert!(encoded.len() < input.len() - article.len());
+    assert_eq!(decode(&encoded), input.as_bytes());
+
+    // without unused bytes '<' would be the rarest, but has to stay out of the control symbols
+    let all_bytes: Vec<u8> = (0..100).flat_map(|_| (0..=255u8).filter(|&c| c != b'<')).chain(input.bytes()).collect();
+    let control_chars = crate::prep::control_symbols_excluding(&all_bytes, CONTROL_COUNT, EXCLUDED);
+    assert!(!control_chars.contains(&b'<'));
+    assert_eq!(decode(&encode(&all_bytes, &control_chars)), all_bytes);
+}
@@ -0,0 +1,126 @@
+use crate::prep::{push_escaped, read_controls, write_controls};
+
+/// named entities replaced by a single control symbol each. the escaped forms as they appear in wiki text
+/// come before `&amp;`, which would match them otherwise.
+const NAMED: [&[u8]; 8] = [b"&amp;nbsp;", b"&amp;mdash;", b"&amp;ndash;", b"&quot;", b"&amp;", b"&lt;", b"&gt;", b"&apos;"];
+
+/// numeric entity forms (prefix and radix), each with its own marker: `&#N;`, `&#xH;` and the same escaped
+/// once more as `&amp;#N;` and `&amp;#xH;`, the spelling of entities in the wiki text of the dumps
+const NUMERIC: [(&[u8], u32); 4] = [(b"&#", 10), (b"&#x", 16), (b"&amp;#", 10), (b"&amp;#x", 16)];
+
+/// number of control symbols `encode` needs: escape, one per numeric form and one per named entity
+pub const CONTROL_COUNT: usize = 1 + NUMERIC.len() + NAMED.len();
+
+fn format_numeric(value: u32, radix: u32) -> String {
+    match radix {
+        16 => format!("{value:X}"),
+        _ => value.to_string()
+    }
+}
+
+/// char of a numeric entity `{prefix}N;` in `radix` at the start of `input` and the entity length.
+/// only the canonical spelling (no leading zeros, upper case hex digits) is accepted, so that decoding restores it exactly.
+fn numeric_entity(input: &[u8], prefix: &[u8], radix: u32) -> Option<(char, usize)> {
+    let body = input.strip_prefix(prefix)?;
+    let digits = body.iter().take_while(|&&c| (c as char).is_digit(radix)).count();
+    if digits == 0 || digits > 7 || body.get(digits) != Some(&b';') { return None }
+    let text = std::str::from_utf8(&body[..digits]).ok()?;
+    let value = u32::from_str_radix(text, radix).ok()?;
+    if format_numeric(value, radix) != text { return None }
+    Some((char::from_u32(value)?, prefix.len()+digits+1))
+}
+
+/// length of the UTF-8 sequence starting with `lead`
+fn utf8_len(lead: u8) -> usize {
+    match lead {
+        0..=0x7F => 1,
+        0x80..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        _ => 4
+    }
+}
+
+/// replaces the `NAMED` entities by the symbols `control_chars[1+NUMERIC.len()..]`, and numeric entities by
+/// the marker of their form in `control_chars[1..]` followed by the UTF-8 encoded char.
+pub fn encode(input: &[u8], control_chars: &[u8]) -> Vec<u8> {
+    let (numeric, named) = control_chars[1..].split_at(NUMERIC.len());
+
+    let mut out: Vec<u8> = Vec::with_capacity(input.len());
+    write_controls(&mut out, control_chars);
+
+    let mut n = 0;
+    while n < input.len() {
+        let ch = input[n];
+        if ch == b'&' {
+            if let Some((k, (c, len))) = NUMERIC.iter().enumerate().find_map(|(k, &(prefix, radix))| Some((k, numeric_entity(&input[n..], prefix, radix)?))) {
+                out.push(numeric[k]);
+                out.extend(c.to_string().as_bytes());
+                n += len;
+                continue
+            }
+            if let Some(k) = NAMED.iter().position(|entity| input[n..].starts_with(entity)) {
+                out.push(named[k]);
+                n += NAMED[k].len();
+                continue
+            }
+        }
+        push_escaped(&mut out, &[ch], control_chars);
+        n += 1;
+    }
+    out
+}
+
+pub fn decode(input: &[u8]) -> Vec<u8> {
+    let (control_chars, mut n) = read_controls(input);
+    let escape = control_chars[0];
+    let (numeric, named) = control_chars[1..].split_at(NUMERIC.len());
+
+    let mut out: Vec<u8> = Vec::with_capacity(2*input.len());
+    while n < input.len() {
+        let ch = input[n];
+        if ch == escape {
+            n += 1;
+            out.push(input[n]);
+        } else if let Some(k) = numeric.iter().position(|&c| c == ch) {
+            let len = utf8_len(input[n+1]);
+            let c = std::str::from_utf8(&input[n+1..n+1+len]).unwrap().chars().next().unwrap();
+            let (prefix, radix) = NUMERIC[k];
+            out.extend(prefix);
+            out.extend(format_numeric(c as u32, radix).as_bytes());
+            out.push(b';');
+            n += len;
+        } else if let Some(k) = named.iter().position(|&c| c == ch) {
+            out.extend(NAMED[k]);
+        } else {
+            out.push(ch);
+        }
+        n += 1;
+    }
+    out
+}
+
+#[test]
+fn encode_decode() {
+    let control_chars = [1u8, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, b'~'];
+    let input = "&lt;ref&gt;A &amp; B&lt;/ref&gt; &quot;x&quot; &#8212; &#233;&#0; &#0233; &#x2014; &#x2c; &#; &nbsp &amp \x01~ &#99999999;".as_bytes();
+    let encoded = encode(input, &control_chars);
+    assert_eq!(&encoded[..14], [13, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, b'~']);
+    assert_eq!(&encoded[14..], "\x0Bref\x0CA \x0A B\x0B/ref\x0C \x09x\x09 \x02— \x02é\x02\0 &#0233; \x03— &#x2c; &#; &nbsp &amp \x01\x01\x01~ &#99999999;".as_bytes());
+    assert_eq!(decode(&encoded), input);
+}
+
+#[test]
+fn encode_decode_dump() {
+    // wiki text of a dump: entities of the wiki markup are escaped once more by the XML export
+    let control_chars = [1u8, 2, 3, 4, 5, 6, 7, 8, 9, 14, 11, 12, 13];
+    let input = crate::fixtures::page("Anarchism", 12, &format!("{} The name comes from [[Greek language|Greek]] &amp;#945;&amp;#957;&amp;#945;&amp;#961;&amp;#967;&amp;#943;&amp;#945;, \"without [[archon]]s\". Anarchism is a [[political philosophy]]&amp;mdash;or rather a group of philosophies&amp;#8212;that rejects [[authority]].&lt;ref&gt;Peter Kropotkin, &quot;Anarchism&quot;, ''Encyclopaedia Britannica'' 1910&amp;nbsp;p.&amp;nbsp;914&lt;/ref&gt; Copyright &amp;#xA9; &amp;amp; &amp;#x00A9;", crate::fixtures::ARTICLE));
+    let input = input.as_bytes();
+    let encoded = encode(input, &control_chars);
+    let text = String::from_utf8_lossy(&encoded[1+control_chars.len()..]);
+    assert!(text.contains("Greek]] \x04α\x04ν\x04α\x04ρ\x04χ\x04ί\x04α, "));
+    assert!(text.contains("philosophy]]\x07or rather a group of philosophies\x04—that"));
+    assert!(text.contains("1910\x06p.\x06914\x0B/ref\x0C Copyright"));
+    assert!(text.contains("Copyright \x05© \x0Eamp; \x0E#x00A9;<"));
+assert!(encoded.len() < input.len() - 100);
+    assert_eq!(decode(&encoded), input);
+}
@@ -0,0 +1,232 @@
+use crate::varint;
+
+/// exact opening tags whose content (up to the next '<') is moved to a stream of its own
+const FIELDS: [&[u8]; 7] = [b"<id>", b"<timestamp>", b"<title>", b"<username>", b"<ip>", b"<comment>", b"<text xml:space=\"preserve\">"];
+const ID: usize = 0;
+const TIMESTAMP: usize = 1;
+
+/// the last of these opening tags decides the id stream of an `<id>`
+const ID_CONTEXTS: [&[u8]; 3] = [b"<page>", b"<revision>", b"<contributor>"];
+
+pub const STREAMS: usize = ID_CONTEXTS.len() + FIELDS.len() - 1;
+pub const STREAM_NAMES: [&str; STREAMS] = ["page_ids", "revision_ids", "contributor_ids", "timestamps", "titles", "usernames", "ips", "comments", "texts"];
+
+/// the dump without field contents, and one stream per field type (see `STREAM_NAMES`)
+pub struct Fields {
+    pub skeleton: Vec<u8>,
+    pub streams: Vec<Vec<u8>>
+}
+
+fn stream_index(field: usize, context: usize) -> usize {
+    match field {
+        ID => context,
+        _ => ID_CONTEXTS.len() + field - 1
+    }
+}
+
+/// entries start with a flag byte: `CODED` for the binary form, `RAW` for content kept as is (terminated by '<')
+const CODED: u8 = 0;
+const RAW: u8 = 1;
+
+fn push_raw(stream: &mut Vec<u8>, content: &[u8]) {
+    stream.extend(content);
+    stream.push(b'<');
+}
+
+fn read_raw<'a>(stream: &'a [u8], pos: &mut usize) -> &'a [u8] {
+    let len = stream[*pos..].iter().position(|&c| c == b'<').unwrap();
+    *pos += len+1;
+    &stream[*pos-len-1..*pos-1]
+}
+
+/// canonical decimal ids become the zigzag varint of the difference to the previous id of their stream
+fn encode_id(stream: &mut Vec<u8>, content: &[u8], previous: &mut u64) {
+    match std::str::from_utf8(content).ok().and_then(|s| s.parse::<u64>().ok()).filter(|id| id.to_string().as_bytes() == content) {
+        Some(id) => {
+            stream.push(CODED);
+            varint::write(stream, varint::zigzag(id.wrapping_sub(*previous) as i64));
+            *previous = id;
+        }
+        None => {
+            stream.push(RAW);
+            push_raw(stream, content);
+        }
+    }
+}
+
+fn decode_id(stream: &[u8], pos: &mut usize, previous: &mut u64, out: &mut Vec<u8>) {
+    *pos += 1;
+    if stream[*pos-1] == CODED {
+        let id = previous.wrapping_add(varint::unzigzag(varint::read(stream, pos)) as u64);
+        out.extend(id.to_string().as_bytes());
+        *previous = id;
+    } else {
+        out.extend(read_raw(stream, pos));
+    }
+}
+
+/// days since 1970-01-01 of a date in the proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era*400;
+    let day_of_year = (153*((month + 9) % 12) + 2)/5 + day - 1;
+    let day_of_era = year_of_era*365 + year_of_era/4 - year_of_era/100 + day_of_year;
+    era*146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era*146097;
+    let year_of_era = (day_of_era - day_of_era/1460 + day_of_era/36524 - day_of_era/146096) / 365;
+    let day_of_year = day_of_era - (365*year_of_era + year_of_era/4 - year_of_era/100);
+    let shifted_month = (5*day_of_year + 2)/153;
+    let day = day_of_year - (153*shifted_month + 2)/5 + 1;
+    let month = (shifted_month + 2) % 12 + 1;
+    (year_of_era + era*400 + (month <= 2) as i64, month, day)
+}
+
+fn format_timestamp(seconds: u32) -> String {
+    let (year, month, day) = civil_from_days(seconds as i64 / 86400);
+    let time = seconds % 86400;
+    format!("{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z", time/3600, time/60 % 60, time % 60)
+}
+
+/// seconds since 1970 of a `YYYY-MM-DDTHH:MM:SSZ` timestamp, if `format_timestamp` restores it exactly
+fn parse_timestamp(content: &[u8]) -> Option<u32> {
+    let text = std::str::from_utf8(content).ok().filter(|text| text.len() == 20)?;
+    let number = |range: std::ops::Range<usize>| text.get(range)?.parse::<i64>().ok();
+    let days = days_from_civil(number(0..4)?, number(5..7)?, number(8..10)?);
+    let seconds = days*86400 + number(11..13)?*3600 + number(14..16)?*60 + number(17..19)?;
+    u32::try_from(seconds).ok().filter(|&seconds| format_timestamp(seconds) == text)
+}
+
+/// routes the contents of the `FIELDS` elements into their streams: ids delta coded per context
+/// (page, revision, contributor), timestamps as 4-byte big endian seconds, everything else '<'-terminated.
+/// contents that do not fit the binary forms are stored raw, so that `merge` restores any input.
+pub fn split(input: &[u8]) -> Fields {
+    let mut fields = Fields { skeleton: Vec::with_capacity(input.len()), streams: vec![Vec::new(); STREAMS] };
+    let mut previous_ids = [0u64; ID_CONTEXTS.len()];
+    let mut context = 0;
+    let mut n = 0;
+    while n < input.len() {
+        if let Some(c) = ID_CONTEXTS.iter().position(|tag| input[n..].starts_with(tag)) {
+            context = c;
+        }
+        match FIELDS.iter().position(|tag| input[n..].starts_with(tag)) {
+            Some(field) => {
+                fields.skeleton.extend(FIELDS[field]);
+                let start = n + FIELDS[field].len();
+                let len = input[start..].iter().position(|&c| c == b'<').unwrap_or(input.len()-start);
+                let content = &input[start..start+len];
+                let stream = &mut fields.streams[stream_index(field, context)];
+                match field {
+                    ID => encode_id(stream, content, &mut previous_ids[context]),
+                    TIMESTAMP => match parse_timestamp(content) {
+                        Some(seconds) => {
+                            stream.push(CODED);
+                            stream.extend(seconds.to_be_bytes());
+                        }
+                        None => {
+                            stream.push(RAW);
+                            push_raw(stream, content);
+                        }
+                    },
+                    _ => push_raw(stream, content)
+                }
+                n = start+len;
+            }
+            None => {
+                fields.skeleton.push(input[n]);
+                n += 1;
+            }
+        }
+    }
+    fields
+}
+
+/// rebuilds the input of `split`, finding the field positions by the same scan over the skeleton
+pub fn merge(fields: &Fields) -> Vec<u8> {
+    let skeleton = &fields.skeleton;
+    let mut out = Vec::with_capacity(skeleton.len() + fields.streams.iter().map(|stream| stream.len()).sum::<usize>());
+    let mut positions = [0usize; STREAMS];
+    let mut previous_ids = [0u64; ID_CONTEXTS.len()];
+    let mut context = 0;
+    let mut n = 0;
+    while n < skeleton.len() {
+        if let Some(c) = ID_CONTEXTS.iter().position(|tag| skeleton[n..].starts_with(tag)) {
+            context = c;
+        }
+        match FIELDS.iter().position(|tag| skeleton[n..].starts_with(tag)) {
+            Some(field) => {
+                out.extend(FIELDS[field]);
+                let s = stream_index(field, context);
+                let (stream, pos) = (&fields.streams[s], &mut positions[s]);
+                match field {
+                    ID => decode_id(stream, pos, &mut previous_ids[context], &mut out),
+                    TIMESTAMP => {
+                        *pos += 1;
+                        if stream[*pos-1] == CODED {
+                            let seconds = u32::from_be_bytes(stream[*pos..*pos+4].try_into().unwrap());
+                            out.extend(format_timestamp(seconds).as_bytes());
+                            *pos += 4;
+                        } else {
+                            out.extend(read_raw(stream, pos));
+                        }
+                    }
+                    _ => out.extend(read_raw(stream, pos))
+                }
+                n += FIELDS[field].len();
+            }
+            None => {
+                out.push(skeleton[n]);
+                n += 1;
+            }
+        }
+    }
+    out
+}
+
+#[test]
+fn split_merge() {
+    let input = br#"<page>
+    <title>AaA</title>
+    <id>1</id>
+    <revision>
+      <id>32899315</id>
+      <timestamp>2005-12-27T18:46:47Z</timestamp>
+      <contributor>
+        <username>Jsmethers</username>
+        <id>614213</id>
+      </contributor>
+      <comment>a &lt;comment&gt;</comment>
+      <text xml:space="preserve">#REDIRECT [[AAA]]</text>
+    </revision>
+  </page>
+  <page>
+    <title>Bb</title>
+    <id>0012</id>
+    <revision>
+      <id>32899310</id>
+      <timestamp>2005-02-29T18:46:47Z</timestamp>
+      <contributor>
+        <ip>127.0.0.1</ip>
+      </contributor>
+      <timestamp>1969-12-31T23:59:59Z</timestamp><timestamp>2000-02-29T00:00:00Z</timestamp><id></id>
+      <text xml:space="preserve" /><text xml:space="preserve">"#.to_vec();
+    let fields = split(&input);
+    assert!(!fields.skeleton.windows(3).any(|w| w == b"AaA"));
+    assert_eq!(fields.streams[1][..5], [CODED, 0xe6, 0x83, 0xb0, 0x1f]);
+    assert_eq!(fields.streams[1][5..], [CODED, 9]);
+    assert_eq!(fields.streams[3][..5], [CODED, 0x43, 0xb1, 0x8c, 0x17]);
+    assert_eq!(fields.streams[4], b"AaA<Bb<");
+    assert_eq!(merge(&fields), input);
+    for len in [0, 10, 150, 420] {
+        assert_eq!(merge(&split(&input[..len])), &input[..len]);
+    }
+
+    for seconds in [0, 951782400, 1135709207, u32::MAX] {
+        assert_eq!(parse_timestamp(format_timestamp(seconds).as_bytes()), Some(seconds));
+    }
+}
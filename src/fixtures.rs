@@ -0,0 +1,38 @@
+/// start of a dump: declaration, DOCTYPE with an internal subset, siteinfo and a first page
+pub const DUMP_HEADER: &[u8] = br#"<?xml version="1.0" encoding="utf-8"?>
+<!DOCTYPE mediawiki [ <!ELEMENT mediawiki (siteinfo, page*)> <!-- a comment with </siteinfo> inside --> ]>
+<mediawiki xmlns="http://www.mediawiki.org/xml/export-0.3/" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xsi:schemaLocation="http://www.mediawiki.org/xml/export-0.3/ http://www.mediawiki.org/xml/export-0.3.xsd" version="0.3" xml:lang="en">
+  <siteinfo>
+    <sitename>Wikipedia</sitename>
+    <base>http://en.wikipedia.org/wiki/Main_Page</base>
+    <generator>MediaWiki 1.6alpha</generator>
+    <case>first-letter</case>
+      <namespaces>
+      <namespace key="-2">Media</namespace>
+      <namespace key="0" />
+      <namespace key="1">Talk</namespace>
+      </namespaces>
+  </siteinfo>
+  <page>
+    <title>AaA</title>
+    <id>1</id>
+    <revision>
+      <id>32899315</id>
+      <timestamp>2005-12-27T18:46:47Z</timestamp>
+      <contributor>
+        <username>Jsmethers</username>
+        <id>614213</id>
+      </contributor>
+      <comment><![CDATA[a </comment> in <CDATA> ]]></comment>
+      <text xml:space="preserve">#REDIRECT [[AAA]] with a < b and a > c</text>
+    </revision>
+  </page>
+"#;
+
+/// wiki text of an article
+pub const ARTICLE: &str = "'''Anarchism''' is a political philosophy encompassing theories and attitudes which consider the state to be unnecessary.";
+
+/// a page in the layout of the dumps, with the revision id derived from the page id
+pub fn page(title: &str, id: usize, text: &str) -> String {
+    format!("  <page>\n    <title>{title}</title>\n    <id>{id}</id>\n    <revision>\n      <id>{}</id>\n      <text xml:space=\"preserve\">{text}</text>\n    </revision>\n  </page>\n", 10*id)
+}
@@ -204,12 +204,18 @@ pub fn count_freqs<I>(input: I) -> HashMap<I::Item, usize> where I: Iterator, I:
     counters
 }
 
-pub fn entropy_info<X>(counters: HashMap<X, usize>) where X: SerializedBits {
+/// order-0 entropy in bits per symbol and the total number of symbols
+pub fn entropy<X>(counters: HashMap<X, usize>) -> (f64, usize) {
     let freqs: Vec<usize> = counters.into_iter().map(|f| f.1).filter(|&f| f!=0).collect();
     let total: usize = freqs.clone().into_iter().sum();
     let totalf = total as f64;
     let logprobs = freqs.into_iter().map(|f| { let p = (f as f64) / totalf; -p*p.log2() });
-    let entropy_symbol: f64 = logprobs.sum();
+    (logprobs.sum(), total)
+}
+
+pub fn entropy_info<X>(counters: HashMap<X, usize>) where X: SerializedBits {
+    let (entropy_symbol, total) = entropy(counters);
+    let totalf = total as f64;
     let ratio = entropy_symbol / (X::bitlen() as f64);
     println!("entropy ~ {:.4} bits / symbol, min.ratio ~ {:.4}", entropy_symbol, ratio);
     let limit = totalf*ratio;
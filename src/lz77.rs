@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+use crate::huffman::count_freqs;
+
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 255;
+const WINDOW: usize = u16::MAX as usize;
+const MAX_CHAIN: usize = 64;
+const HASH_BITS: u32 = 16;
+/// the parse runs on blocks of this size, so that matches and costs need memory only per block.
+/// matches still reach back into the previous block.
+const BLOCK: usize = 1 << 20;
+const NONE: u32 = u32::MAX;
+
+/// tokens of a parse in three separate streams, so that each can get its own Huffman tree.
+/// `lengths` has one entry per token: 0 takes the next literal, any other value is a match of that
+/// length with the next entry of `distances`.
+pub struct Streams {
+    pub literals: Vec<u8>,
+    pub lengths: Vec<u8>,
+    pub distances: Vec<u16>
+}
+
+fn hash3(input: &[u8], i: usize) -> usize {
+    let x = (input[i] as u32) << 16 | (input[i+1] as u32) << 8 | input[i+2] as u32;
+    (x.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+}
+
+/// hash chains over the whole input. `prev` only covers the window, as older positions are never followed.
+struct Chains {
+    head: Vec<u32>,
+    prev: Vec<u32>
+}
+
+/// matches of the positions of one block in a flat arena: those of position `start+k` are
+/// `lens[offsets[k]..offsets[k+1]]` and the same range of `dists`
+struct Matches {
+    offsets: Vec<u32>,
+    lens: Vec<u8>,
+    dists: Vec<u16>
+}
+
+impl Matches {
+    fn at(&self, k: usize) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let range = self.offsets[k] as usize..self.offsets[k+1] as usize;
+        self.lens[range.clone()].iter().zip(&self.dists[range]).map(|(&len, &dist)| (len as usize, dist as usize))
+    }
+}
+
+/// all matches at the positions `start..end` with strictly increasing lengths, found by hash chains.
+/// matches do not extend beyond `end`.
+fn find_matches(input: &[u8], start: usize, end: usize, chains: &mut Chains) -> Matches {
+    let mut matches = Matches { offsets: Vec::with_capacity(end-start+1), lens: Vec::new(), dists: Vec::new() };
+    for i in start..end {
+        matches.offsets.push(matches.lens.len() as u32);
+        if i + MIN_MATCH > input.len() { continue }
+        let h = hash3(input, i);
+        let max_len = MAX_MATCH.min(end-i);
+        let mut best = MIN_MATCH-1;
+        let mut j = chains.head[h];
+        for _ in 0..MAX_CHAIN {
+            if j == NONE || i - j as usize > WINDOW { break }
+            let j_pos = j as usize;
+            let len = input[j_pos..].iter().zip(&input[i..i+max_len]).take_while(|(a, b)| a == b).count();
+            if len > best {
+                best = len;
+                matches.lens.push(len as u8);
+                matches.dists.push((i-j_pos) as u16);
+                if len == max_len { break }
+            }
+            j = chains.prev[j_pos % (WINDOW+1)];
+        }
+        chains.prev[i % (WINDOW+1)] = chains.head[h];
+        chains.head[h] = i as u32;
+    }
+    matches.offsets.push(matches.lens.len() as u32);
+    matches
+}
+
+/// estimated code lengths in bits per symbol of each stream
+struct Costs {
+    literals: Vec<f64>,
+    lengths: Vec<f64>,
+    distances: HashMap<u16, f64>,
+    unseen_distance: f64
+}
+
+impl Costs {
+    /// initial guess before any statistics are known
+    fn fixed() -> Self {
+        let mut lengths = vec![8.0; 256];
+        lengths[0] = 1.0;
+        Self { literals: vec![8.0; 256], lengths, distances: HashMap::new(), unseen_distance: 16.0 }
+    }
+
+    /// code lengths from the symbol frequencies of a previous parse, smoothed by add-one counts
+    fn from_streams(streams: &Streams) -> Self {
+        fn bits<X: std::hash::Hash + Eq>(freqs: &HashMap<X, usize>, total: usize, symbol: &X, alphabet: usize) -> f64 {
+            let count = freqs.get(symbol).copied().unwrap_or(0) as f64;
+            -((count + 1.0) / (total + alphabet) as f64).log2()
+        }
+        let lit_freqs = count_freqs(streams.literals.iter().copied());
+        let len_freqs = count_freqs(streams.lengths.iter().copied());
+        let dist_freqs = count_freqs(streams.distances.iter().copied());
+        let (lit_total, len_total, dist_total) = (streams.literals.len(), streams.lengths.len(), streams.distances.len());
+
+        let literals = (0..=255u8).map(|c| bits(&lit_freqs, lit_total, &c, 256)).collect();
+        let lengths = (0..=255u8).map(|l| bits(&len_freqs, len_total, &l, 256)).collect();
+        let distances = dist_freqs.keys().map(|&d| (d, bits(&dist_freqs, dist_total, &d, WINDOW))).collect();
+        let unseen_distance = bits(&dist_freqs, dist_total, &0, WINDOW);
+        Self { literals, lengths, distances, unseen_distance }
+    }
+
+    fn literal(&self, c: u8) -> f64 {
+        self.lengths[0] + self.literals[c as usize]
+    }
+
+    fn copy(&self, len: usize, dist: usize) -> f64 {
+        self.lengths[len] + self.distances.get(&(dist as u16)).copied().unwrap_or(self.unseen_distance)
+    }
+}
+
+/// cheapest parse of the block `input` under `costs` by dynamic programming over all positions
+fn optimal_parse(input: &[u8], matches: &Matches, costs: &Costs) -> Streams {
+    let n = input.len();
+    let mut cost = vec![f64::INFINITY; n+1];
+    let mut choice = vec![(0usize, 0usize); n+1]; // (length, distance) of the token ending here
+    cost[0] = 0.0;
+
+    for i in 0..n {
+        let lit = cost[i] + costs.literal(input[i]);
+        if lit < cost[i+1] {
+            cost[i+1] = lit;
+            choice[i+1] = (0, 0);
+        }
+        let mut shorter = MIN_MATCH-1;
+        for (len, dist) in matches.at(i) {
+            for l in shorter+1..=len {
+                let c = cost[i] + costs.copy(l, dist);
+                if c < cost[i+l] {
+                    cost[i+l] = c;
+                    choice[i+l] = (l, dist);
+                }
+            }
+            shorter = len;
+        }
+    }
+
+    let mut tokens = Vec::new();
+    let mut i = n;
+    while i > 0 {
+        let (len, dist) = choice[i];
+        tokens.push((i, len, dist));
+        i -= len.max(1);
+    }
+
+    let mut streams = Streams { literals: Vec::new(), lengths: Vec::new(), distances: Vec::new() };
+    for (end, len, dist) in tokens.into_iter().rev() {
+        streams.lengths.push(len as u8);
+        if len == 0 {
+            streams.literals.push(input[end-1]);
+        } else {
+            streams.distances.push(dist as u16);
+        }
+    }
+    streams
+}
+
+/// LZ77 parse with hash-chain match finding, block by block. in every block a first parse with fixed costs
+/// provides the symbol statistics for a second, cost-based optimal parse.
+pub fn encode(input: &[u8]) -> Streams {
+    encode_blocks(input, BLOCK)
+}
+
+fn encode_blocks(input: &[u8], block_size: usize) -> Streams {
+    assert!(input.len() < NONE as usize, "positions are stored as u32");
+    let mut chains = Chains { head: vec![NONE; 1 << HASH_BITS], prev: vec![NONE; WINDOW+1] };
+    let mut streams = Streams { literals: Vec::new(), lengths: Vec::new(), distances: Vec::new() };
+    for start in (0..input.len()).step_by(block_size) {
+        let end = input.len().min(start+block_size);
+        let matches = find_matches(input, start, end, &mut chains);
+        let first = optimal_parse(&input[start..end], &matches, &Costs::fixed());
+        let block = optimal_parse(&input[start..end], &matches, &Costs::from_streams(&first));
+        streams.literals.extend(block.literals);
+        streams.lengths.extend(block.lengths);
+        streams.distances.extend(block.distances);
+    }
+    streams
+}
+
+pub fn decode(streams: &Streams) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut literals = streams.literals.iter();
+    let mut distances = streams.distances.iter();
+    for &len in &streams.lengths {
+        if len == 0 {
+            out.push(*literals.next().unwrap());
+        } else {
+            let start = out.len() - *distances.next().unwrap() as usize;
+            for k in 0..len as usize {
+                out.push(out[start+k]);
+            }
+        }
+    }
+    out
+}
+
+#[test]
+fn encode_decode() {
+    let input = b"<page><title>a</title></page>\n<page><title>b</title></page>\n<page><title>aaaaaaaaaaaaaaaaaaaaaaaa</title></page>".to_vec();
+    let streams = encode(&input);
+    assert!(streams.lengths.len() < input.len()/2);
+    assert_eq!(streams.lengths.iter().filter(|&&l| l != 0).count(), streams.distances.len());
+    assert_eq!(decode(&streams), input);
+
+    // matches reach back across block boundaries
+    for block_size in [1, 7, 40] {
+        assert_eq!(decode(&encode_blocks(&input, block_size)), input);
+    }
+    assert!(encode_blocks(&input, 40).lengths.len() < input.len()/2);
+}
@@ -0,0 +1,81 @@
+const ORDER: usize = 4;
+const HASH_BITS: u32 = 22;
+
+/// hash of the `ORDER` bytes preceding position i
+fn context_hash(input: &[u8], i: usize) -> usize {
+    let x = u32::from_le_bytes([input[i-4], input[i-3], input[i-2], input[i-1]]);
+    (x.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+}
+
+/// LZ-prediction: the position that followed the current order-4 context last time predicts the
+/// following bytes. wherever a prediction exists, the number of correctly predicted bytes is written
+/// (in chunks of 255, a chunk below 255 ends the length), followed by the literal that broke the match.
+/// positions without prediction are written as plain literals. long matches give runs of zero
+/// lengths, which suits `rlencode<-` and the Huffman modes.
+pub fn encode(input: &[u8]) -> Vec<u8> {
+    let mut table = vec![usize::MAX; 1 << HASH_BITS];
+    let mut out = Vec::with_capacity(input.len());
+    let mut i = 0;
+    while i < input.len() {
+        if i >= ORDER {
+            let h = context_hash(input, i);
+            let predicted = table[h];
+            table[h] = i;
+            if predicted != usize::MAX {
+                let len = input[predicted..].iter().zip(&input[i..]).take_while(|(a, b)| a == b).count();
+                let mut rest = len;
+                while rest >= 255 {
+                    out.push(255);
+                    rest -= 255;
+                }
+                out.push(rest as u8);
+                i += len;
+                if i == input.len() { break }
+            }
+        }
+        out.push(input[i]);
+        i += 1;
+    }
+    out
+}
+
+pub fn decode(codes: &[u8]) -> Vec<u8> {
+    let mut table = vec![usize::MAX; 1 << HASH_BITS];
+    let mut out: Vec<u8> = Vec::with_capacity(2*codes.len());
+    let mut c = 0;
+    while c < codes.len() {
+        let i = out.len();
+        if i >= ORDER {
+            let h = context_hash(&out, i);
+            let predicted = table[h];
+            table[h] = i;
+            if predicted != usize::MAX {
+                let mut len = 0;
+                loop {
+                    let chunk = codes[c] as usize;
+                    c += 1;
+                    len += chunk;
+                    if chunk < 255 { break }
+                }
+                for k in 0..len {
+                    out.push(out[predicted+k]);
+                }
+                if c == codes.len() { break }
+            }
+        }
+        out.push(codes[c]);
+        c += 1;
+    }
+    out
+}
+
+#[test]
+fn encode_decode() {
+    let mut input = b"<page><title>a</title></page>\n<page><title>b</title></page>\n".to_vec();
+    input.extend([b'x'; 600]);
+    input.extend(b"<page><title>c</title></page>");
+    let codes = encode(&input);
+    assert!(codes.len() < input.len()/4);
+    assert_eq!(decode(&codes), input);
+    assert_eq!(decode(&encode(b"abc")), b"abc");
+}
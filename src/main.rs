@@ -3,15 +3,47 @@ use std::fs::{read,write};
 use std::io::{Result, ErrorKind, Error};
 use std::env;
 use std::path::Path;
+use std::time::Instant;
 
 mod huffman;
-use huffman::{count_freqs, entropy_info, encode, decode, HuffmanNode};
+use huffman::{count_freqs, entropy, entropy_info, encode, decode, HuffmanNode};
 
 mod prob;
-use prob::{encode as prob_encode, decode as prob_decode};
+use prob::{encode as prob_encode, encode_windowed as prob_encode_windowed, decode_windowed as prob_decode_windowed, encode_blocks as prob_encode_blocks, decode_blocks as prob_decode_blocks, encode_checkpointed as prob_encode_checkpointed, resume as prob_resume, window_for_budget, UNBOUNDED};
+
+mod bwt;
+
+mod bpe;
+
+mod lz77;
+
+mod lzp;
+
+mod tokens;
+
+mod xwrt;
+
+mod entities;
+
+mod wiki;
+
+mod varint;
+
+mod fields;
+
+mod schema;
+
+mod dedup;
+
+mod rzip;
+
+mod reorder;
 
 mod prep;
-use prep::{prepare, unprepare, unused_symbols};
+use prep::{prepare, unprepare, unused_symbols, control_symbols, control_symbols_excluding, learn_tags, case_mode_gain, FIXED_CONTROLS};
+
+#[cfg(test)]
+mod fixtures;
 
 fn read_u16<P>(path: P) -> Result<Vec<u16>> where P: AsRef<Path> {
     let contents = read(path)?;
@@ -24,12 +56,57 @@ fn write_u16<P>(path: P, contents: Vec<u16>) -> Result<()> where P: AsRef<Path>
     write(path, contents_u8)
 }
 
+/// largest search window of the rank transform that fits a memory budget given in MiB, unbounded without budget
+fn window_arg<X>(budget_mb: Option<String>, nm_end: usize, reference_len: usize) -> Result<usize> where X: prob::Symbol {
+    match budget_mb {
+        Some(mb) => window_for_budget::<X>(nm_end, reference_len, mb.parse::<usize>().unwrap() << 20)
+            .ok_or(Error::new(ErrorKind::OutOfMemory, format!("memory budget of {mb} MiB too small for input"))),
+        None => Ok(UNBOUNDED)
+    }
+}
+
 fn main() -> Result<()> {
     const ENWIK9: &str = "../enwik9";
     const UNUSED_FILE: &str = "unused.u8";
+    // provisional: to be chosen from the losses probblocks<- reports on enwik9, which have not been measured yet
+    const DEFAULT_BLOCK_SIZE: usize = 1 << 24;
+    const DEFAULT_CHECKPOINT_INTERVAL: usize = 1 << 24;
     let prepd_file = "out/enwik.prepd";
     let probcodes_file = "out/probcodes.u8";
     let probcodes_file_d = "out/probcodes.u8.d";
+    let checkpoint_file = "out/probcodes.ckpt";
+    let probcodes16_file = "out/probcodes.u16";
+    let probcodes16_file_d = "out/probcodes.u16.d";
+    let bwt_file = "out/bwt.u16";
+    let bwt_file_d = "out/bwt.u16.d";
+    let lz_literals_file = "out/lz.lit";
+    let lz_lengths_file = "out/lz.len";
+    let lz_distances_file = "out/lz.dist";
+    let lzp_file = "out/lzp.u8";
+    let lzp_file_d = "out/lzp.u8.d";
+    let bpe_file = "out/bpe.u16";
+    let bpe_file_d = "out/bpe.u16.d";
+    let bpe_rules_file = "out/bpe.rules";
+    let tokens_file = "out/tokens.u16";
+    let tokens_file_d = "out/tokens.u16.d";
+    let vocabulary_file = "out/tokens.vocab";
+    let xwrt_file = "out/xwrt.u8";
+    let xwrt_file_d = "out/xwrt.u8.d";
+    let entities_file = "out/entities.u8";
+    let entities_file_d = "out/entities.u8.d";
+    let wiki_file = "out/wiki.u8";
+    let wiki_file_d = "out/wiki.u8.d";
+    let fields_skeleton_file = "out/fields.skeleton";
+    let schema_structure_file = "out/schema.structure";
+    let schema_tags_file = "out/schema.tags";
+    let schema_text_file = "out/schema.text";
+    let dedup_file = "out/dedup.u8";
+    let dedup_file_d = "out/dedup.u8.d";
+    let rzip_literals_file = "out/rzip.lit";
+    let rzip_refs_file = "out/rzip.refs";
+    let reorder_file = "out/reorder.u8";
+    let reorder_file_d = "out/reorder.u8.d";
+    let permutation_file = "out/reorder.perm";
     let rle_file = "out/rle.u16";
     let rle_file_d = "out/rle.u16.d";
     let hufftree_file = "out/huffcodes.tree";
@@ -46,22 +123,282 @@ fn main() -> Result<()> {
         }
         "prepd<-enwik" => {
             let max_len = usize::from_str_radix(&args.next().unwrap(), 10).unwrap();
-            let mut input = read(ENWIK9)?;
+            // optional: source file replacing enwik9, e.g. the output of entities<-
+            let mut input = read(args.next().unwrap_or(ENWIK9.to_owned()))?;
             input.truncate(max_len);
 
-            let unused = read(UNUSED_FILE)?;
-            let mut out = prepare(&input, &unused);
+            // as many tags as there are unused bytes left for them, control chars and tags go into the header
+            let tags = learn_tags(&input, unused_symbols(&input).len().saturating_sub(FIXED_CONTROLS));
+            let mut out = prepare(&input, &tags);
             out.reverse();
 
             write(prepd_file, &out)
         }
+        "capsgain<-enwik" => {
+            let max_len = args.next().unwrap().parse::<usize>().unwrap();
+            let mut input = read(ENWIK9)?;
+            input.truncate(max_len);
+
+            let (letters, words) = case_mode_gain(&input);
+            println!("prepd without tags: {letters} bytes with capitals per letter, {words} bytes with word case modes ({} saved)", letters as isize - words as isize);
+            Ok(())
+        }
         "probencode<-" => {
             let prepd_filename = args.next().unwrap();
 
             let prepd = read(prepd_filename).unwrap();
-            let probcodes = prob_encode(prepd);
+            // optional: checkpoint interval (0 disables checkpoints), memory budget in MiB
+            let interval = args.next().map_or(0, |arg| arg.parse::<usize>().unwrap());
+            let window = window_arg::<u8>(args.next(), prepd.len(), 0)?;
+            let probcodes = match interval {
+                0 => prob_encode_windowed(prepd, &[], window),
+                _ => prob_encode_checkpointed(prepd, window, checkpoint_file, interval)?
+            };
+            write(probcodes_file, probcodes)
+        }
+        "probdelta<-" => {
+            // delta compression against a reference, e.g. a prepd older revision of the same dump
+            let prepd_filename = args.next().unwrap();
+            let reference_filename = args.next().unwrap();
+
+            let prepd = read(prepd_filename)?;
+            let reference = read(reference_filename)?;
+            let window = window_arg::<u8>(args.next(), prepd.len(), reference.len())?;
+            let probcodes = prob_encode_windowed(prepd, &reference, window);
+            write(probcodes_file, probcodes)
+        }
+        "probencode16<-" => {
+            // u16 counterpart of probencode<-: expects reversed symbols (e.g. from tokenize<-)
+            let filename = args.next().unwrap();
+
+            let reversed = read_u16(filename)?;
+            // optional: memory budget in MiB
+            let window = window_arg::<u16>(args.next(), reversed.len(), 0)?;
+            let probcodes = prob_encode_windowed(reversed, &[], window);
+            write_u16(probcodes16_file, probcodes)
+        }
+        "probresume<-" => {
+            let interval = args.next().map_or(DEFAULT_CHECKPOINT_INTERVAL, |arg| arg.parse::<usize>().unwrap());
+            let probcodes = prob_resume(checkpoint_file, interval)?;
+            write(probcodes_file, probcodes)
+        }
+        "probencodeblocks<-" => {
+            let prepd_filename = args.next().unwrap();
+            let block_size = args.next().map_or(DEFAULT_BLOCK_SIZE, |arg| arg.parse::<usize>().unwrap());
+
+            let prepd = read(prepd_filename)?;
+            let probcodes = prob_encode_blocks(prepd, block_size);
             write(probcodes_file, probcodes)
         }
+        "probblocks<-" => {
+            // order-0 entropy limit of the probcodes for several block sizes, relative to a single block
+            let prepd_filename = args.next().unwrap();
+            let prepd = read(prepd_filename)?;
+
+            let limit_bytes = |probcodes: &[u8]| { let (h, total) = entropy(count_freqs(probcodes.iter())); h*(total as f64)/8.0 };
+            let unblocked = limit_bytes(&prob_encode(prepd.clone()));
+            println!("unblocked: limit ~ {:.1} bytes", unblocked);
+
+            for arg in args {
+                let block_size = arg.parse::<usize>().unwrap();
+                let probcodes = prob_encode_blocks(prepd.clone(), block_size);
+                let index_len = 4*(u32::from_le_bytes([probcodes[0],probcodes[1],probcodes[2],probcodes[3]]) as usize + 1);
+                let blocked = limit_bytes(&probcodes[index_len..]) + index_len as f64;
+                println!("block size {}: limit ~ {:.1} bytes, loss {:+.3}%", block_size, blocked, 100.0*(blocked/unblocked - 1.0));
+            }
+            Ok(())
+        }
+        "bwtencode<-" => {
+            // alternative to probcodes: prepd -> bwt -> mtf -> zero runs, then huffencode16<-
+            let filename = args.next().unwrap();
+            let block_size = args.next().map_or(bwt::BLOCK_SIZE, |arg| arg.parse::<usize>().unwrap());
+            let input = read(filename)?;
+
+            let start = Instant::now();
+            let codes = bwt::encode(&input, block_size);
+            println!("bwt+mtf+zrle: {} -> {} symbols in {:.2?}", input.len(), codes.len(), start.elapsed());
+            write_u16(bwt_file, codes)
+        }
+        "lzencode<-" => {
+            // writes separate literal (u8), length (u8) and distance (u16) streams for the huffencode modes
+            let filename = args.next().unwrap();
+            let input = read(filename)?;
+
+            let start = Instant::now();
+            let streams = lz77::encode(&input);
+            println!("lz77: {} -> {} tokens in {:.2?}", input.len(), streams.lengths.len(), start.elapsed());
+            for (name, stream) in [("literals", &streams.literals), ("lengths", &streams.lengths)] {
+                println!("{name}:");
+                entropy_info(count_freqs(stream.iter().copied()));
+            }
+            println!("distances:");
+            entropy_info(count_freqs(streams.distances.iter().copied()));
+
+            write(lz_literals_file, streams.literals)?;
+            write(lz_lengths_file, streams.lengths)?;
+            write_u16(lz_distances_file, streams.distances)
+        }
+        "lzpencode<-" => {
+            let filename = args.next().unwrap();
+            let input = read(filename)?;
+
+            let start = Instant::now();
+            let codes = lzp::encode(&input);
+            println!("lzp: {} -> {} bytes in {:.2?}", input.len(), codes.len(), start.elapsed());
+            write(lzp_file, codes)
+        }
+        "bpeencode<-" => {
+            let filename = args.next().unwrap();
+            let max_rules = args.next().map_or(4096, |arg| arg.parse::<usize>().unwrap());
+            let input = read(filename)?;
+
+            let start = Instant::now();
+            let (seq, rules) = bpe::encode(&input, &unused_symbols(&input), max_rules);
+            println!("bpe: {} -> {} symbols with {} rules in {:.2?}", input.len(), seq.len(), rules.len(), start.elapsed());
+            write(bpe_rules_file, bpe::rules_to_bytes(&rules))?;
+            write_u16(bpe_file, seq)
+        }
+        "tokenize<-" => {
+            // u16 word tokens for entropy16<-, huffencode16<- and probencode16<-, reversed like the prepd file
+            let filename = args.next().unwrap();
+            let max_words = args.next().map_or(u16::MAX as usize, |arg| arg.parse::<usize>().unwrap());
+            let input = read(filename)?;
+
+            let vocabulary = tokens::build_vocabulary(&input, max_words);
+            let mut tokens = tokens::tokenize(&input, &vocabulary);
+            println!("tokenize: {} bytes -> {} tokens, vocabulary of {} words", input.len(), tokens.len(), vocabulary.len());
+            tokens.reverse();
+            write(vocabulary_file, tokens::vocabulary_to_bytes(&vocabulary))?;
+            write_u16(tokens_file, tokens)
+        }
+        "xwrtdict<-" => {
+            let sample_filename = args.next().unwrap();
+            let dictionary_filename = args.next().unwrap();
+            let max_words = args.next().map_or(4096, |arg| arg.parse::<usize>().unwrap());
+
+            let sample = read(sample_filename)?;
+            write(dictionary_filename, xwrt::build_dictionary(&sample, max_words))
+        }
+        "xwrt<-" => {
+            // byte stream with dictionary words replaced, for probencode<- or huffencode8<-
+            let filename = args.next().unwrap();
+            let dictionary_filename = args.next().unwrap();
+            let input = read(filename)?;
+            let dictionary = read(dictionary_filename)?;
+
+            let control_chars = control_symbols(&input, xwrt::control_count(&dictionary));
+            let out = xwrt::encode(&input, &dictionary, &control_chars);
+            println!("xwrt: {} -> {} bytes", input.len(), out.len());
+            write(xwrt_file, out)
+        }
+        "entities<-" => {
+            // byte stream with entities replaced, for prepd<-enwik with this file as source
+            let filename = args.next().unwrap();
+            let input = read(filename)?;
+
+            let control_chars = control_symbols(&input, entities::CONTROL_COUNT);
+            let out = entities::encode(&input, &control_chars);
+            println!("entities: {} -> {} bytes", input.len(), out.len());
+            write(entities_file, out)
+        }
+        "wiki<-" => {
+            // byte stream with wiki markup replaced, for prepd<-enwik with this file as source
+            let filename = args.next().unwrap();
+            let input = read(filename)?;
+
+            let control_chars = control_symbols(&input, wiki::CONTROL_COUNT);
+            let out = wiki::encode(&input, &control_chars);
+            println!("wiki: {} -> {} bytes", input.len(), out.len());
+            write(wiki_file, out)
+        }
+        "fields<-" => {
+            // skeleton and one stream per field type (out/fields.<name>), each for its own huffencode8<- or probencode<-
+            let filename = args.next().unwrap();
+            let input = read(filename)?;
+
+            let fields = fields::split(&input);
+            for (name, stream) in [("skeleton", &fields.skeleton)].into_iter().chain(fields::STREAM_NAMES.into_iter().zip(&fields.streams)) {
+                println!("{name}: {} bytes", stream.len());
+                entropy_info(count_freqs(stream.iter().copied()));
+            }
+            for (name, stream) in fields::STREAM_NAMES.iter().zip(fields.streams) {
+                write(format!("out/fields.{name}"), stream)?;
+            }
+            write(fields_skeleton_file, fields.skeleton)
+        }
+        "schema<-" => {
+            // markup as deviations from the predicted tags, text separately, e.g. on out/fields.skeleton
+            let filename = args.next().unwrap();
+            let input = read(filename)?;
+
+            let skeleton = schema::encode(&input);
+            for (name, stream) in [("structure", &skeleton.structure), ("tags", &skeleton.tags), ("text", &skeleton.text)] {
+                println!("{name}: {} bytes", stream.len());
+                entropy_info(count_freqs(stream.iter().copied()));
+            }
+            write(schema_structure_file, skeleton.structure)?;
+            write(schema_tags_file, skeleton.tags)?;
+            write(schema_text_file, skeleton.text)
+        }
+        "dedup<-" => {
+            // page bodies as references to earlier ones, for prepd<-enwik with this file as source
+            let filename = args.next().unwrap();
+            let input = read(filename)?;
+
+            let control_chars = control_symbols_excluding(&input, dedup::CONTROL_COUNT, dedup::EXCLUDED);
+            let out = dedup::encode(&input, &control_chars);
+            println!("dedup: {} -> {} bytes", input.len(), out.len());
+            write(dedup_file, out)
+        }
+        "rzip<-" => {
+            // long repeats removed, e.g. from a prepd file: the literals keep its order and can go to probencode<-
+            let filename = args.next().unwrap();
+            let input = read(filename)?;
+
+            let start = Instant::now();
+            let streams = rzip::encode(&input);
+            println!("rzip: {} -> {} literal bytes, {} ref bytes in {:.2?}", input.len(), streams.literals.len(), streams.refs.len(), start.elapsed());
+            write(rzip_literals_file, streams.literals)?;
+            write(rzip_refs_file, streams.refs)
+        }
+        "reorder<-" => {
+            // pages reordered by title or shingles, reordergain<-enwik reports the gain
+            let filename = args.next().unwrap();
+            let order = match args.next().as_deref() {
+                Some("shingles") => reorder::Order::Shingles,
+                _ => reorder::Order::Title
+            };
+            let input = read(filename)?;
+
+            let (out, permutation) = reorder::encode(&input, order);
+            println!("reorder: permutation of {} bytes", permutation.len());
+            write(permutation_file, permutation)?;
+            write(reorder_file, out)
+        }
+        "reordergain<-enwik" => {
+            // prepd -> probcodes -> Huffman sizes of the original and the reordered dump
+            let max_len = args.next().unwrap().parse::<usize>().unwrap();
+            let order = match args.next().as_deref() {
+                Some("shingles") => reorder::Order::Shingles,
+                _ => reorder::Order::Title
+            };
+            let mut input = read(ENWIK9)?;
+            input.truncate(max_len);
+
+            let compressed_len = |input: &[u8]| {
+                let tags = learn_tags(input, unused_symbols(input).len().saturating_sub(FIXED_CONTROLS));
+                let mut prepd = prepare(input, &tags);
+                prepd.reverse();
+                let probcodes = prob_encode(prepd);
+                let tree = HuffmanNode::from_weights(count_freqs(probcodes.iter().copied()));
+                encode(probcodes.into_iter(), &tree).len()
+            };
+            let (reordered, permutation) = reorder::encode(&input, order);
+            let original = compressed_len(&input);
+            let reordered = compressed_len(&reordered) + permutation.len();
+            println!("huffman-coded probcodes (without tree): {original} bytes in original order, {reordered} bytes reordered including the permutation ({} saved)", original as isize - reordered as isize);
+            Ok(())
+        }
         "rlencode<-" => {
             let filename = args.next().unwrap();
             let mut content = read(filename)?.into_iter();
@@ -150,6 +487,95 @@ fn main() -> Result<()> {
             let output = decode(&input, tree);
             write_u16(filename, output)
         }
+        "bwtdecode->" => {
+            let filename = args.next().unwrap();
+            let codes = read_u16(bwt_file_d)?;
+
+            let start = Instant::now();
+            let output = bwt::decode(&codes);
+            println!("unbwt: {} symbols in {:.2?}", output.len(), start.elapsed());
+            write(filename, output)
+        }
+        "lzdecode->" => {
+            let filename = args.next().unwrap();
+            let streams = lz77::Streams {
+                literals: read(lz_literals_file.to_owned()+".d")?,
+                lengths: read(lz_lengths_file.to_owned()+".d")?,
+                distances: read_u16(lz_distances_file.to_owned()+".d")?
+            };
+            write(filename, lz77::decode(&streams))
+        }
+        "lzpdecode->" => {
+            let filename = args.next().unwrap();
+            let codes = read(lzp_file_d)?;
+            write(filename, lzp::decode(&codes))
+        }
+        "bpedecode->" => {
+            let filename = args.next().unwrap();
+            let seq = read_u16(bpe_file_d)?;
+            let rules = bpe::rules_from_bytes(&read(bpe_rules_file)?);
+            write(filename, bpe::decode(&seq, &rules))
+        }
+        "detokenize->" => {
+            let filename = args.next().unwrap();
+            let mut tokens = read_u16(tokens_file_d)?;
+            tokens.reverse();
+            let vocabulary = tokens::vocabulary_from_bytes(&read(vocabulary_file)?);
+            write(filename, tokens::detokenize(&tokens, &vocabulary))
+        }
+        "unxwrt->" => {
+            let filename = args.next().unwrap();
+            let dictionary_filename = args.next().unwrap();
+            let input = read(xwrt_file_d)?;
+            let dictionary = read(dictionary_filename)?;
+            write(filename, xwrt::decode(&input, &dictionary))
+        }
+        "unentities->" => {
+            let filename = args.next().unwrap();
+            let input = read(entities_file_d)?;
+            write(filename, entities::decode(&input))
+        }
+        "unwiki->" => {
+            let filename = args.next().unwrap();
+            let input = read(wiki_file_d)?;
+            write(filename, wiki::decode(&input))
+        }
+        "fields->" => {
+            let filename = args.next().unwrap();
+            let fields = fields::Fields {
+                skeleton: read(fields_skeleton_file.to_owned()+".d")?,
+                streams: fields::STREAM_NAMES.iter().map(|name| read(format!("out/fields.{name}.d"))).collect::<Result<_>>()?
+            };
+            write(filename, fields::merge(&fields))
+        }
+        "schema->" => {
+            let filename = args.next().unwrap();
+            let skeleton = schema::Skeleton {
+                structure: read(schema_structure_file.to_owned()+".d")?,
+                tags: read(schema_tags_file.to_owned()+".d")?,
+                text: read(schema_text_file.to_owned()+".d")?
+            };
+            write(filename, schema::decode(&skeleton))
+        }
+        "undedup->" => {
+            let filename = args.next().unwrap();
+            let input = read(dedup_file_d)?;
+            write(filename, dedup::decode(&input))
+        }
+        "rzip->" => {
+            let filename = args.next().unwrap();
+            let streams = rzip::Streams {
+                literals: read(rzip_literals_file.to_owned()+".d")?,
+                refs: read(rzip_refs_file.to_owned()+".d")?
+            };
+            write(filename, rzip::decode(&streams))
+        }
+        "unreorder->" => {
+            let filename = args.next().unwrap();
+            let input = read(reorder_file_d)?;
+            let permutation = read(permutation_file)?;
+            write(filename, reorder::decode(&input, &permutation))
+        }
         "rldecode->" => {
             let filename = args.next().unwrap();
             let rle = read(rle_file_d)?;
@@ -167,7 +593,30 @@ fn main() -> Result<()> {
             let filename = args.next().unwrap();
 
             let probcodes = read(probcodes_file_d)?;
-            let prepd = prob_decode(&probcodes);
+            let prepd = prob_decode_windowed(&probcodes, &[]);
+            write(filename, prepd)
+        }
+        "probdeltadecode->" => {
+            let filename = args.next().unwrap();
+            let reference_filename = args.next().unwrap();
+
+            let probcodes = read(probcodes_file_d)?;
+            let reference = read(reference_filename)?;
+            let prepd = prob_decode_windowed(&probcodes, &reference);
+            write(filename, prepd)
+        }
+        "probdecode16->" => {
+            let filename = args.next().unwrap();
+
+            let probcodes = read_u16(probcodes16_file_d)?;
+            let reversed = prob_decode_windowed(&probcodes, &[]);
+            write_u16(filename, reversed)
+        }
+        "probdecodeblocks->" => {
+            let filename = args.next().unwrap();
+
+            let probcodes = read(probcodes_file_d)?;
+            let prepd = prob_decode_blocks(&probcodes);
             write(filename, prepd)
         }
         "unprep->" => {
@@ -176,8 +625,7 @@ fn main() -> Result<()> {
             let mut input = read(prepd_file.to_owned()+".d")?;
             input.reverse(); 
             
-            let unused = read(UNUSED_FILE)?;
-            let out = unprepare(&input, &unused);
+            let out = unprepare(&input);
 
             write(filename, out)
         }
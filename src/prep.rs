@@ -1,4 +1,5 @@
-use std::collections::HashSet;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
 
 fn order_symbols(symbols: HashSet<u8>) -> Vec<u8> {
     let mut s : Vec<u8> = symbols.into_iter().collect();
@@ -14,97 +15,571 @@ pub fn unused_symbols(content: &[u8]) -> Vec<u8> {
     order_symbols(symbols)
 }
 
-pub fn prepare(input: &[u8], control_chars: &[u8]) -> Vec<u8> {
+/// `count` symbols for control purposes: unused symbols first, then the least frequent ones.
+/// any used symbol among them has to be escaped by the transform using it.
+pub fn control_symbols(content: &[u8], count: usize) -> Vec<u8> {
+    control_symbols_excluding(content, count, &[])
+}
+
+/// `control_symbols` without the symbols in `excluded`, for transforms that rely on some bytes keeping their meaning
+pub fn control_symbols_excluding(content: &[u8], count: usize, excluded: &[u8]) -> Vec<u8> {
+    let mut freqs = [0usize; 256];
+    for &ch in content {
+        freqs[ch as usize] += 1;
+    }
+    let mut symbols: Vec<u8> = (0..=255u8).filter(|ch| !excluded.contains(ch)).collect();
+    symbols.sort_by_key(|&ch| freqs[ch as usize]);
+    symbols.truncate(count);
+    symbols
+}
+
+/// stores the control symbols of a transform in front of its output: their number, then the symbols
+pub fn write_controls(out: &mut Vec<u8>, control_chars: &[u8]) {
+    out.push(control_chars.len() as u8);
+    out.extend(control_chars);
+}
+
+/// the control symbols stored by `write_controls` at the start of `input`, and the position after them
+pub fn read_controls(input: &[u8]) -> (&[u8], usize) {
+    let count = input[0] as usize;
+    (&input[1..1+count], 1+count)
+}
+
+/// appends `bytes`, each control symbol among them preceded by the escape `control_chars[0]`
+pub fn push_escaped(out: &mut Vec<u8>, bytes: &[u8], control_chars: &[u8]) {
+    for &ch in bytes {
+        if control_chars.contains(&ch) { out.push(control_chars[0]); }
+        out.push(ch);
+    }
+}
+
+fn is_name_start(ch: u8) -> bool {
+    ch.is_ascii_alphabetic() || ch == b'_' || ch == b':' || ch >= 0x80
+}
+
+fn tag_name(tag: &[u8]) -> Vec<u8> {
+    tag.iter().copied().take_while(|&c| !c.is_ascii_whitespace() && c != b'/' && c != b'>').collect()
+}
+
+/// incremental XML tokenizer tracking the stack of open elements. `prepare` and `unprepare` both feed it
+/// the original bytes, so that they see the same stack. comments, CDATA sections, processing instructions
+/// and declarations (`<!DOCTYPE ...>`, also with an internal subset) are skipped, as is a `<` that cannot
+/// start markup. markup that is still open at the end of the input simply never completes.
+#[derive(Default)]
+struct TagStack {
+    markup: Vec<u8>, // current markup starting at '<', empty in text
+    quote: Option<u8>,
+    tags: Vec<Vec<u8>>
+}
+
+impl TagStack {
+    fn in_text(&self) -> bool {
+        self.markup.is_empty()
+    }
+
+    fn top(&self) -> Option<&[u8]> {
+        self.tags.last().map(|tag| &tag[..])
+    }
+
+    fn restart(&mut self, ch: u8) {
+        self.markup.clear();
+        self.quote = None;
+        if ch == b'<' { self.markup.push(ch) }
+    }
+
+    /// returns the complete tag if `ch` ended an opening (or empty-element) tag
+    fn feed(&mut self, ch: u8) -> Option<Vec<u8>> {
+        if self.markup.is_empty() {
+            if ch == b'<' { self.markup.push(ch) }
+            return None
+        }
+        self.markup.push(ch);
+        let m = &self.markup;
+
+        if m.len() == 2 && !(ch == b'/' || ch == b'!' || ch == b'?' || is_name_start(ch)) {
+            self.restart(ch);
+        } else if m.starts_with(b"<!--") {
+            if m.len() >= 7 && m.ends_with(b"-->") { self.restart(ch) }
+        } else if m.starts_with(b"<![CDATA[") {
+            if m.len() >= 12 && m.ends_with(b"]]>") { self.restart(ch) }
+        } else if b"<!--".starts_with(m) || b"<![CDATA[".starts_with(m) {
+            // not yet decided
+        } else if m.starts_with(b"<?") {
+            if m.len() >= 4 && m.ends_with(b"?>") { self.restart(ch) }
+        } else if m.starts_with(b"<!") {
+            let depth = |c| m.iter().filter(|&&x| x == c).count();
+            if ch == b'>' && depth(b'[') == depth(b']') { self.restart(ch) }
+        } else if let Some(q) = self.quote {
+            if ch == q { self.quote = None }
+        } else if (ch == b'"' || ch == b'\'') && m[1] != b'/' {
+            self.quote = Some(ch);
+        } else if ch == b'<' {
+            self.restart(ch);
+        } else if ch == b'>' {
+            if m[1] == b'/' {
+                let name = tag_name(&m[2..]);
+                if let Some(pos) = self.tags.iter().rposition(|tag| *tag == name) {
+                    self.tags.truncate(pos);
+                }
+            } else {
+                if !m.ends_with(b"/>") {
+                    let name = tag_name(&m[1..]);
+                    self.tags.push(name);
+                }
+                let tag = std::mem::take(&mut self.markup);
+                self.restart(ch);
+                return Some(tag)
+            }
+            self.restart(ch);
+        }
+        None
+    }
+}
+
+/// the most frequent opening tags (including attributes) of `input`, ordered by the bytes saved when
+/// each is replaced by a single control symbol
+pub fn learn_tags(input: &[u8], max_tags: usize) -> Vec<Vec<u8>> {
+    let mut stack = TagStack::default();
+    let mut counts: HashMap<Vec<u8>, usize> = HashMap::new();
+    for &ch in input {
+        if let Some(tag) = stack.feed(ch) {
+            *counts.entry(tag).or_default() += 1;
+        }
+    }
+    let mut tags: Vec<(Vec<u8>, usize)> = counts.into_iter().filter(|&(_, count)| count >= 2).collect();
+    tags.sort_by_key(|(tag, count)| (Reverse(count*(tag.len()-1)), tag.clone()));
+    tags.truncate(max_tags);
+    tags.into_iter().map(|(tag, _)| tag).collect()
+}
+
+/// tag table as stored next to the prepd output, each tag preceded by its length as u32 (little endian)
+pub fn tags_to_bytes(tags: &[Vec<u8>]) -> Vec<u8> {
+    tags.iter().flat_map(|tag| (tag.len() as u32).to_le_bytes().into_iter().chain(tag.iter().copied())).collect()
+}
+
+pub fn tags_from_bytes(bytes: &[u8]) -> Vec<Vec<u8>> {
+    let mut tags = Vec::new();
+    let mut n = 0;
+    while n < bytes.len() {
+        let len = u32::from_le_bytes(bytes[n..n+4].try_into().unwrap()) as usize;
+        tags.push( bytes[n+4..n+4+len].to_vec() );
+        n += 4+len;
+    }
+    tags
+}
+
+/// lookup table of the control chars
+fn control_table(controls: &[u8]) -> [bool; 256] {
+    let mut is_control = [false; 256];
+    for &c in controls {
+        is_control[c as usize] = true;
+    }
+    is_control
+}
+
+/// whether `input` starts with the closing tag of the innermost open element. mismatched closing tags
+/// and closing tags without open element are kept literally, and `TagStack` handles them the same way
+/// on both sides.
+fn closes_innermost(input: &[u8], stack: &TagStack) -> Option<usize> {
+    let top = stack.top()?;
+    let len = top.len() + 3;
+    let matches = input.len() >= len && input.starts_with(b"</") && input[2..len-1] == *top && input[len-1] == b'>';
+    if matches { Some(len) } else { None }
+}
+
+/// length of an all-caps word (a run of at least two ASCII letters, all uppercase) at the start of `input`
+fn caps_word_len(input: &[u8]) -> Option<usize> {
+    let len = input.iter().take_while(|c| c.is_ascii_alphabetic()).count();
+    if len >= 2 && input[..len].iter().all(|c| c.is_ascii_uppercase()) { Some(len) } else { None }
+}
+
+/// uppercase code points folded by `prepare`, as (first, last, stride, offset to lowercase).
+/// the lowercase ranges do not overlap, so folding is exactly reversible.
+const CASE_RANGES: [(u32, u32, u32, u32); 16] = [
+    (0xC0, 0xD6, 1, 0x20), (0xD8, 0xDE, 1, 0x20), // Latin-1
+    (0x100, 0x12E, 2, 1), (0x132, 0x136, 2, 1), (0x139, 0x147, 2, 1), (0x14A, 0x176, 2, 1), (0x179, 0x17D, 2, 1), // Latin Extended-A
+    (0x386, 0x386, 1, 0x26), (0x388, 0x38A, 1, 0x25), (0x38C, 0x38C, 1, 0x40), (0x38E, 0x38F, 1, 0x3F), (0x391, 0x3A1, 1, 0x20), (0x3A3, 0x3AB, 1, 0x20), // Greek
+    (0x400, 0x40F, 1, 0x50), (0x410, 0x42F, 1, 0x20), (0x460, 0x480, 2, 1) // Cyrillic
+];
+
+/// code point of a 2-byte UTF-8 sequence at the start of `input`
+fn decode_utf8_2(input: &[u8]) -> Option<u32> {
+    match input {
+        [lead @ 0xC2..=0xDF, cont @ 0x80..=0xBF, ..] => Some(((*lead as u32 & 0x1F) << 6) | (*cont as u32 & 0x3F)),
+        _ => None
+    }
+}
+
+fn encode_utf8_2(cp: u32) -> [u8; 2] {
+    [0xC0 | (cp >> 6) as u8, 0x80 | (cp & 0x3F) as u8]
+}
+
+/// lowercase bytes of an uppercase 2-byte UTF-8 char at the start of `input`
+fn fold_utf8(input: &[u8]) -> Option<[u8; 2]> {
+    let cp = decode_utf8_2(input)?;
+    CASE_RANGES.iter().find(|&&(first, last, stride, _)| (first..=last).contains(&cp) && (cp-first) % stride == 0)
+        .map(|&(_, _, _, offset)| encode_utf8_2(cp + offset))
+}
+
+/// inverse of `fold_utf8`
+fn unfold_utf8(input: &[u8]) -> Option<[u8; 2]> {
+    let cp = decode_utf8_2(input)?;
+    CASE_RANGES.iter().find(|&&(first, last, stride, offset)| (first+offset..=last+offset).contains(&cp) && (cp-first-offset) % stride == 0)
+        .map(|&(_, _, _, offset)| encode_utf8_2(cp - offset))
+}
+
+/// lengths of the digit runs `prepare` packs into binary. shorter runs (like years) would not get
+/// shorter, longer ones do not fit the value bits.
+const NUMBER_DIGITS: std::ops::RangeInclusive<usize> = 5..=15;
+
+/// bits of the largest value with `digits` digits
+fn value_bits(digits: usize) -> usize {
+    64 - (10u64.pow(digits as u32) - 1).leading_zeros() as usize
+}
+
+/// packed form of a digit run: the digit count in the upper 4 bits of the first byte, then the value
+/// (big endian), so that leading zeros survive
+fn pack_number(digits: &[u8]) -> Vec<u8> {
+    let len = (4 + value_bits(digits.len())).div_ceil(8);
+    let value: u64 = std::str::from_utf8(digits).unwrap().parse().unwrap();
+    let packed = (digits.len() as u64) << (8*len - 4) | value;
+    packed.to_be_bytes()[8-len..].to_vec()
+}
+
+/// digits of a packed number at the start of `input` and the packed length
+fn unpack_number(input: &[u8]) -> (Vec<u8>, usize) {
+    let digits = (input[0] >> 4) as usize;
+    let len = (4 + value_bits(digits)).div_ceil(8);
+    let packed = input[..len].iter().fold(0u64, |x, &c| x << 8 | c as u64);
+    let value = packed & ((1 << (8*len - 4)) - 1);
+    (format!("{value:0digits$}").into_bytes(), len)
+}
+
+/// control chars before the tag symbols: xml_end, big_char, escape, caps_word, caps_run, caps_end, number
+pub const FIXED_CONTROLS: usize = 7;
+
+/// prepares `input` with control chars of its own (see `control_symbols`): unused bytes where there are
+/// enough, otherwise also the rarest ones, whose occurrences get escaped. the header holds the number of
+/// control chars, the control chars, the length of the tag table (u32 LE) and the table, so that
+/// `unprepare` needs nothing else. only as many tags are used as there are unused bytes left after the
+/// `FIXED_CONTROLS`, so that only those can fall back to escaping.
+pub fn prepare(input: &[u8], tags: &[Vec<u8>]) -> Vec<u8> {
+    let mut used = [false; 256];
+    input.iter().for_each(|&c| used[c as usize] = true);
+    let free = used.iter().filter(|&&u| !u).count().saturating_sub(FIXED_CONTROLS).min(255 - FIXED_CONTROLS);
+    let tags = &tags[..tags.len().min(free)];
+    let control_chars = control_symbols(input, FIXED_CONTROLS + tags.len());
+    let tag_bytes = tags_to_bytes(tags);
+
+    let mut out = Vec::new();
+    write_controls(&mut out, &control_chars);
+    out.extend((tag_bytes.len() as u32).to_le_bytes());
+    out.extend(tag_bytes);
+    out.extend(prepare_with(input, &control_chars, tags));
+    out
+}
+
+pub fn unprepare(input: &[u8]) -> Vec<u8> {
+    let (control_chars, n) = read_controls(input);
+    let tags_len = u32::from_le_bytes(input[n..n+4].try_into().unwrap()) as usize;
+    let tags = tags_from_bytes(&input[n+4..n+4+tags_len]);
+    unprepare_with(&input[n+4+tags_len..], control_chars, &tags)
+}
+
+/// `tags` (see `learn_tags`) are replaced by the control chars following the first seven,
+/// so `control_chars` needs at least 7+tags.len() entries.
+fn prepare_with(input: &[u8], control_chars: &[u8], tags: &[Vec<u8>]) -> Vec<u8> {
+    prepare_cases(input, control_chars, tags, true)
+}
+
+/// sizes of the prepd `input` (without tag symbols) with capitals only marked letter by letter,
+/// and with the word-level case modes
+pub fn case_mode_gain(input: &[u8]) -> (usize, usize) {
+    let control_chars = control_symbols(input, FIXED_CONTROLS);
+    (prepare_cases(input, &control_chars, &[], false).len(), prepare_cases(input, &control_chars, &[], true).len())
+}
+
+fn prepare_cases(input: &[u8], control_chars: &[u8], tags: &[Vec<u8>], case_modes: bool) -> Vec<u8> {
     let xml_end = control_chars[0]; // used for v1
     let big_char = control_chars[1]; //used for v1+v2
+    let escape = control_chars[2]; // precedes input bytes equal to a control char
+    let caps_word = control_chars[3]; // the following lowercase letters are all uppercase
+    let caps_run = control_chars[4]; // all letters up to caps_end are uppercase
+    let caps_end = control_chars[5];
+    let number = control_chars[6]; // followed by a packed digit run
+    let is_control = control_table(&control_chars[..FIXED_CONTROLS+tags.len()]);
+    let tag_chars = &control_chars[FIXED_CONTROLS..];
+
+    // tags by the byte following their '<', in the order of `tags`
+    let mut tags_by_next: Vec<Vec<usize>> = vec![Vec::new(); 256];
+    for (k, tag) in tags.iter().enumerate() {
+        tags_by_next[tag[1] as usize].push(k);
+    }
 
     let mut out: Vec<u8> = Vec::with_capacity(input.len());
+    let mut stack = TagStack::default();
     let mut n = 0;
-    loop {
+    while n < input.len() {
         let ch = input[n];
-        let to_push = match ch {
-            b'<' => {
-                if input[n+1] != b'/' {
-                    ch
-                } else {
-                    n += 2;
-                    while input[n] != b'>' && n<input.len() {
-                        n += 1;
-                    }
-                    xml_end
+        if ch.is_ascii_digit() && (n == 0 || !input[n-1].is_ascii_digit()) {
+            let len = input[n..].iter().take_while(|c| c.is_ascii_digit()).count();
+            if NUMBER_DIGITS.contains(&len) {
+                input[n..n+len].iter().for_each(|&c| { stack.feed(c); });
+                out.push(number);
+                out.extend(pack_number(&input[n..n+len]));
+                n += len;
+                continue
+            }
+        }
+        if ch == b'<' && stack.in_text() {
+            if let Some(len) = closes_innermost(&input[n..], &stack) {
+                input[n..n+len].iter().for_each(|&c| { stack.feed(c); });
+                out.push(xml_end);
+                n += len;
+                continue
+            }
+            let candidates = input.get(n+1).map_or(&[][..], |&next| &tags_by_next[next as usize]);
+            if let Some(k) = candidates.iter().copied().find(|&k| input[n..].starts_with(&tags[k])) {
+                tags[k].iter().for_each(|&c| { stack.feed(c); });
+                out.push(tag_chars[k]);
+                n += tags[k].len();
+                continue
+            }
+        }
+
+        if let Some(word_len) = caps_word_len(&input[n..]).filter(|_| case_modes) {
+            // extend to a run of all-caps words separated by single spaces
+            let (mut span, mut words) = (word_len, 1);
+            while input.get(n+span) == Some(&b' ') {
+                match caps_word_len(&input[n+span+1..]) {
+                    Some(len) => { span += 1+len; words += 1; }
+                    None => break
                 }
             }
-            65..=90 => {
-                out.push(big_char);
-                ch+32 //.to_lowercase
+            let lowered: Vec<u8> = input[n..n+span].to_ascii_lowercase();
+            if !lowered.iter().any(|&c| is_control[c as usize]) {
+                input[n..n+span].iter().for_each(|&c| { stack.feed(c); });
+                if words == 1 {
+                    out.push(caps_word);
+                    out.extend(lowered);
+                } else {
+                    out.push(caps_run);
+                    out.extend(lowered);
+                    out.push(caps_end);
+                }
+                n += span;
+                continue
             }
-            _ => ch
-        };
-        out.push( to_push );
+        }
 
+        if let Some(lower) = fold_utf8(&input[n..]) {
+            input[n..n+2].iter().for_each(|&c| { stack.feed(c); });
+            out.push(big_char);
+            out.extend(lower);
+            n += 2;
+            continue
+        }
+
+        stack.feed(ch);
+        if ch.is_ascii_uppercase() {
+            out.push(big_char);
+            out.push(ch+32); //.to_lowercase
+        } else {
+            if is_control[ch as usize] { out.push(escape); }
+            out.push(ch);
+        }
         n += 1;
-        if n>=input.len() { break; }
     }
     out
 }
 
-fn fetch_xml_tag(input: &[u8]) -> Option<Vec<u8>> {
-    assert_eq!(input[0],b'<');
-    let mut b=2;
-    let mut c=0;
-    while input[b] != b'>' {
-        if c==0 && input[b] == b' ' { c = b; }
-        b += 1;
-        if b>=input.len() { break; }
-    }
-    if input[b-1] == b'/' { return None }
-    if c != 0 { b = c; }
-    Some( input[1..b].to_vec() )
-}
-
-pub fn unprepare(input: &[u8], control_chars: &[u8]) -> Vec<u8> {
+fn unprepare_with(input: &[u8], control_chars: &[u8], tags: &[Vec<u8>]) -> Vec<u8> {
     let xml_end = control_chars[0]; // used for v1
     let big_char = control_chars[1]; //used for v1+v2
+    let escape = control_chars[2];
+    let caps_word = control_chars[3];
+    let caps_run = control_chars[4];
+    let caps_end = control_chars[5];
+    let number = control_chars[6];
+    let is_control = control_table(&control_chars[..FIXED_CONTROLS+tags.len()]);
+    let mut tag_of = [None; 256];
+    for (k, &c) in control_chars[FIXED_CONTROLS..FIXED_CONTROLS+tags.len()].iter().enumerate() {
+        tag_of[c as usize] = Some(k);
+    }
 
     let mut out: Vec<u8> = Vec::with_capacity(input.len());
-
-    let mut xml_tags: Vec<Vec<u8>> = Vec::new();
+    let mut stack = TagStack::default();
     let mut n = 0;
-    loop {
+    while n < input.len() {
         let ch = input[n];
-        match ch {
-            b'<' => {
-                match fetch_xml_tag(&input[n..]) {
-                    Some(xml_tag) => xml_tags.push( xml_tag ),
-                    None => { }
-                }
-            }
-            _ => ()
-        }
-
-        let to_push =
-        if ch == big_char {
-            n += 1; input[n]-32 //.to_uppercase
+        if ch == number {
+            let (digits, len) = unpack_number(&input[n+1..]);
+            digits.iter().for_each(|&c| { stack.feed(c); });
+            out.extend(digits);
+            n += len;
         } else if ch == xml_end {
-            out.extend(b"</");
-            out.extend( xml_tags.pop().unwrap() );
-            b'>'
+            let mut tag = b"</".to_vec();
+            tag.extend(stack.top().unwrap());
+            tag.push(b'>');
+            tag.iter().for_each(|&c| { stack.feed(c); });
+            out.extend(tag);
+        } else if let Some(k) = tag_of[ch as usize] {
+            tags[k].iter().for_each(|&c| { stack.feed(c); });
+            out.extend(&tags[k]);
+        } else if ch == caps_word {
+            while let Some(&c) = input.get(n+1).filter(|&&c| c.is_ascii_lowercase() && !is_control[c as usize]) {
+                stack.feed(c-32);
+                out.push(c-32);
+                n += 1;
+            }
+        } else if ch == caps_run {
+            n += 1;
+            while input[n] != caps_end {
+                let c = input[n].to_ascii_uppercase();
+                stack.feed(c);
+                out.push(c);
+                n += 1;
+            }
+        } else if ch == big_char && input[n+1] >= 0x80 {
+            let upper = unfold_utf8(&input[n+1..]).unwrap();
+            upper.iter().for_each(|&c| { stack.feed(c); });
+            out.extend(upper);
+            n += 2;
         } else {
-            ch
-        }; 
-        out.push(to_push);
+            let to_push = if ch == escape {
+                n += 1; input[n]
+            } else if ch == big_char {
+                n += 1; input[n]-32 //.to_uppercase
+            } else {
+                ch
+            };
+            stack.feed(to_push);
+            out.push(to_push);
+        }
         n += 1;
-        if n>=input.len() { break; }
     }
     out
 }
 
+#[cfg(test)]
+use crate::fixtures::DUMP_HEADER;
+
 #[test]
 fn prepare_unprepare() {
-    let control_chars = vec![b'~', 1u8];
+    let control_chars = vec![b'~', 1u8, 2, 3, 4, 5, 6];
     let input = b"<one tag><another tag/>Hi<third tg 2start>this is a test for Basic xml tagging</third> and cApital Letter detection</one>".to_vec();
-    let prepd = prepare(&input, &control_chars);
-    let output = unprepare(&prepd, &control_chars);
+    let prepd = prepare_with(&input, &control_chars, &[]);
+    let output = unprepare_with(&prepd, &control_chars, &[]);
     //although assert_eq!(input,output) possible, the following gives better debug info:
-    assert_eq!(String::from_utf8_lossy(&input), String::from_utf8_lossy(&output))
+    assert_eq!(String::from_utf8_lossy(&input), String::from_utf8_lossy(&output));
+    assert_eq!(String::from_utf8_lossy(&input), String::from_utf8_lossy(&unprepare(&prepare(&input, &[]))))
+}
+
+#[test]
+fn prepare_unprepare_dump() {
+    let control_chars = vec![1u8, 2, 3, 4, 5, 6, 7];
+    let prepd = prepare_with(DUMP_HEADER, &control_chars, &[]);
+    // every closing tag outside the comment and CDATA section is replaced
+    assert_eq!(prepd.iter().filter(|&&c| c == control_chars[0]).count(), 19);
+    assert_eq!(String::from_utf8_lossy(DUMP_HEADER), String::from_utf8_lossy(&unprepare_with(&prepd, &control_chars, &[])));
+
+    // buffers ending within a tag or directly after '<'
+    for cut in [DUMP_HEADER.len()-8, DUMP_HEADER.len()-12, 39, 1] {
+        let input = [&DUMP_HEADER[..cut], b"<"].concat();
+        let output = unprepare_with(&prepare_with(&input, &control_chars, &[]), &control_chars, &[]);
+        assert_eq!(String::from_utf8_lossy(&input), String::from_utf8_lossy(&output));
+    }
+    assert!(prepare_with(b"", &control_chars, &[]).is_empty());
+}
+
+#[test]
+fn prepare_unprepare_malformed() {
+    let control_chars = vec![1u8, 2, 3, 4, 5, 6, 7];
+    let input = b"<a><b>x</a>y</b></c>z</ >\x01\x02\x03 \x02A</a x><a></a>".to_vec();
+    let prepd = prepare_with(&input, &control_chars, &[]);
+    // only the final </a> closes the innermost element, the other 1u8 is an escaped literal
+    assert_eq!(prepd.iter().filter(|&&c| c == control_chars[0]).count(), 2);
+    assert!(prepd.windows(6).any(|w| w == [3, 1, 3, 2, 3, 3]));
+    assert_eq!(String::from_utf8_lossy(&input), String::from_utf8_lossy(&unprepare_with(&prepd, &control_chars, &[])));
+}
+
+#[test]
+fn prepare_unprepare_tags() {
+    let control_chars = vec![1u8, 2, 3, 4, 5, 6, 7, 8, 9, 10];
+    let input = [DUMP_HEADER, DUMP_HEADER].concat();
+    let tags = learn_tags(&input, 3);
+    assert_eq!(tags.len(), 3);
+    assert!(tags[0].starts_with(b"<mediawiki xmlns="));
+    assert_eq!(tags[1], b"<text xml:space=\"preserve\">");
+    assert_eq!(tags_from_bytes(&tags_to_bytes(&tags)), tags);
+
+    let prepd = prepare_with(&input, &control_chars, &tags);
+    assert_eq!(prepd.iter().filter(|&&c| c == control_chars[8]).count(), 2);
+    assert_eq!(String::from_utf8_lossy(&input), String::from_utf8_lossy(&unprepare_with(&prepd, &control_chars, &tags)));
+
+    // tags longer than 65535 bytes keep their full length in the tag table
+    let long_tag = [b"<a title=\"".to_vec(), vec![b'x'; 70000], b"\">".to_vec()].concat();
+    let input = [&long_tag[..], b"one</a>", &long_tag, b"two</a>"].concat();
+    let tags = learn_tags(&input, 3);
+    assert_eq!(tags[0], long_tag);
+    assert_eq!(tags_from_bytes(&tags_to_bytes(&tags)), tags);
+    assert_eq!(unprepare_with(&prepare_with(&input, &control_chars, &tags), &control_chars, &tags), input);
+}
+
+#[test]
+fn prepare_unprepare_cases() {
+    let control_chars = vec![1u8, 2, 3, 4, 5, 6, b'q'];
+    let input = b"The NASA and USA, THE UNITED STATES OF AMERICA. McDONALD x A I AM qQ QQ QQQ ALL CAPS\x05".to_vec();
+    let prepd = prepare_with(&input, &control_chars, &[]);
+    assert!(prepd.starts_with(b"\x02the \x04nasa and \x04usa, \x05the united states of america\x06."));
+    assert_eq!(String::from_utf8_lossy(&input), String::from_utf8_lossy(&unprepare_with(&prepd, &control_chars, &[])));
+
+    let (letters, words) = case_mode_gain(&input);
+    assert!(words < letters);
+}
+
+#[test]
+fn prepare_unprepare_utf8() {
+    let control_chars = vec![1u8, 2, 3, 4, 5, 6, 7];
+    let input = "Ärger in Österreich, Ŀ Ĳ Ÿ ΆΘΗΝΑ Ωmega, Москва ЁЛКА Ѡ × ÷ \u{1E9E}".as_bytes();
+    let input = [input, &[0xC3, b'x', 0xC3], &"Ä".as_bytes()[..1]].concat();
+    let prepd = prepare_with(&input, &control_chars, &[]);
+    assert!(prepd.starts_with("\x02ärger in \x02österreich".as_bytes()));
+    assert!(String::from_utf8_lossy(&prepd).contains("\x02ωmega, \x02москва"));
+    assert_eq!(unprepare_with(&prepd, &control_chars, &[]), input);
+
+    for cp in 0x80..0x800 {
+        let bytes = encode_utf8_2(cp);
+        if let Some(lower) = fold_utf8(&bytes) {
+            assert_eq!(unfold_utf8(&lower), Some(bytes));
+            assert_eq!(String::from_utf8_lossy(&lower), String::from_utf8_lossy(&bytes).to_lowercase());
+        }
+    }
+}
+
+#[test]
+fn prepare_unprepare_numbers() {
+    let control_chars = vec![1u8, 2, 3, 4, 5, 6, 7];
+    let input = b"born 1952, population 12345 or 00012 and 999999999999999, id 32899315; 1234567890123456 x7 12345".to_vec();
+    let prepd = prepare_with(&input, &control_chars, &[]);
+    assert!(prepd.starts_with(b"born 1952, population \x07\x50\x30\x39 or \x07\x50\x00\x0c and \x07\xf3\x8d\x7e\xa4\xc6\x7f\xff"));
+    assert!(prepd.len() < input.len() - 12);
+    assert_eq!(String::from_utf8_lossy(&input), String::from_utf8_lossy(&unprepare_with(&prepd, &control_chars, &[])));
+}
+
+#[test]
+fn prepare_unprepare_header() {
+    let input = [DUMP_HEADER, DUMP_HEADER].concat();
+    let tags = learn_tags(&input, 3);
+    let prepd = prepare(&input, &tags);
+    assert_eq!(prepd[0] as usize, FIXED_CONTROLS + 3);
+    assert_eq!(prepd[1..11], control_symbols(&input, FIXED_CONTROLS + 3));
+    assert_eq!(unprepare(&prepd), input);
+
+    // without unused bytes, the rarest bytes become control chars and get escaped
+    let all_bytes: Vec<u8> = (0..=255u8).chain(input.iter().copied()).collect();
+    let prepd = prepare(&all_bytes, &tags);
+    assert_eq!(prepd[0] as usize, FIXED_CONTROLS); // no unused bytes left for tags
+    assert_eq!(unprepare(&prepd), all_bytes);
+    assert_eq!(unprepare(&prepare(b"", &[])), b"");
 }
\ No newline at end of file
@@ -1,32 +1,103 @@
-use std::{cmp::Reverse, collections::HashMap};
+use std::{cmp::Reverse, collections::{HashMap, VecDeque}, fs, hash::Hash, io, mem::size_of, path::Path, thread};
 use indicatif::{ProgressBar, ProgressStyle};
 
-struct RotundHelper {
-    reversed: Vec<u8>,
-    cache: HashMap<u8,Vec<usize>>
+/// symbol types the rank transform works on. ranks are emitted in the same type as the symbols,
+/// so every rank below the alphabet size has to be representable by `from_index`.
+pub trait Symbol: Copy + Eq + Hash + Send + Sync {
+    /// number of distinct symbols
+    const ALPHABET: usize;
+    fn index(self) -> usize;
+    fn from_index(index: usize) -> Self;
 }
 
-impl RotundHelper {
-    pub fn new(reversed: Vec<u8>) -> Self {
-        Self { reversed, cache: HashMap::new() }
+impl Symbol for u8 {
+    const ALPHABET: usize = 256;
+    fn index(self) -> usize { self as usize }
+    fn from_index(index: usize) -> Self { index as u8 }
+}
+
+impl Symbol for u16 {
+    const ALPHABET: usize = 65536;
+    fn index(self) -> usize { self as usize }
+    fn from_index(index: usize) -> Self { index as u16 }
+}
+
+/// window size without any limit on the number of remembered positions
+pub const UNBOUNDED: usize = usize::MAX;
+
+const CHUNK_LEN: usize = 1024;
+
+/// alphabets up to this size keep the per-symbol statistics of `make_rotund` in an array
+const DENSE_ALPHABET: usize = 256;
+
+/// positions of a symbol in descending order, stored as u32 offsets in fixed-size chunks.
+/// chunks avoid the temporary doubling of memory when a large Vec grows, and allow dropping the oldest positions.
+#[derive(Default)]
+struct PositionList {
+    chunks: VecDeque<Vec<u32>>,
+    head: usize // positions already dropped from the first chunk
+}
+
+impl PositionList {
+    fn push(&mut self, x: usize) {
+        match self.chunks.back_mut() {
+            Some(chunk) if chunk.len() < CHUNK_LEN => chunk.push(x as u32),
+            _ => {
+                let mut chunk = Vec::with_capacity(CHUNK_LEN);
+                chunk.push(x as u32);
+                self.chunks.push_back(chunk);
+            }
+        }
     }
 
-    fn make_rotund(&self, n: usize) -> Vec<u8> {
-        let content = &self.reversed[n..];
+    fn pop_oldest(&mut self) {
+        self.head += 1;
+        if self.head == CHUNK_LEN {
+            self.chunks.pop_front();
+            self.head = 0;
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.chunks.iter().flatten().skip(self.head).map(|&x| x as usize)
+    }
+}
 
-        let mut rotund_overlap = [0; 256];
-        let mut rotund_freq = [0u32; 256];
+struct RotundHelper<X> {
+    reversed: Vec<X>,
+    cache: HashMap<X,PositionList>,
+    window: usize
+}
+
+impl<X> RotundHelper<X> where X: Symbol {
+    pub fn new(reversed: Vec<X>, window: usize) -> Self {
+        assert!(reversed.len() <= u32::MAX as usize, "positions are stored as u32");
+        Self { reversed, cache: HashMap::new(), window }
+    }
 
-        let first = content[0];
+    /// helper whose history already contains `reference`, which is treated as text directly preceding
+    /// the input. both are reversed, so the reference is appended behind the input.
+    pub fn primed(mut reversed: Vec<X>, reference: &[X], window: usize) -> Self {
+        let nm_end = reversed.len();
+        reversed.extend_from_slice(reference);
+        let mut helper = Self::new(reversed, window);
+        for x in (nm_end..helper.reversed.len()).rev() {
+            helper.add_to_cache(x);
+        }
+        helper
+    }
+
+    /// calls `visit` with the target symbol and the context overlap of every remembered match of position n
+    fn for_each_match(&self, n: usize, mut visit: impl FnMut(X, usize)) {
+        let content = &self.reversed[n..];
         let clen = content.len();
 
-        let vn: Vec<usize> = Vec::new();
-        let entries = match self.cache.get(&first) {
+        let entries = match self.cache.get(&content[0]) {
             Some(entr) => entr,
-            None => &vn
+            None => return
         };
 
-        for x in entries {
+        for x in entries.iter() {
             let xs = x-n;
             let mut overlap = 1;
             loop {
@@ -34,29 +105,72 @@ impl RotundHelper {
                 if xo >= clen || content[overlap] != content[xo] { break; }
                 overlap += 1;
             }
+            visit(content[xs-1], overlap);
+        }
+    }
 
-            let target = content[xs-1] as usize;
-
-            if rotund_overlap[target] > overlap {
-                continue
+    /// symbols that occurred after a previous match of the current context, most probable first.
+    /// all other symbols of the alphabet follow implicitly in ascending order, see `rank` and `symbol`.
+    fn make_rotund(&self, n: usize) -> Vec<X> {
+        fn update(target: &mut (usize, u32), overlap: usize) {
+            if target.0 > overlap {
+                return
             }
-            if rotund_overlap[target] == overlap {
-                rotund_freq[target] += 1;
+            if target.0 == overlap {
+                target.1 += 1;
             } else {
-                rotund_overlap[target] = overlap;
-                rotund_freq[target] = 1;
+                *target = (overlap, 1);
             }
         }
 
-        let mut keys : Vec<u8> = (0..=255u8).collect();
-        keys.sort_by_key(|&x| { let xi=x as usize; Reverse((rotund_overlap[xi] << 32) +(rotund_freq[xi] as usize)) });
+        // (overlap, frequency) per target symbol: a plain array for bytes, sparse for large alphabets
+        let mut keys: Vec<X>;
+        if X::ALPHABET <= DENSE_ALPHABET {
+            let mut rotund = [(0usize, 0u32); DENSE_ALPHABET];
+            self.for_each_match(n, |target, overlap| update(&mut rotund[target.index()], overlap));
+            keys = (0..X::ALPHABET).filter(|&i| rotund[i].1 > 0).map(X::from_index).collect();
+            keys.sort_by_key(|&x| (Reverse(rotund[x.index()]), x.index()));
+        } else {
+            let mut rotund: HashMap<X,(usize,u32)> = HashMap::new();
+            self.for_each_match(n, |target, overlap| update(rotund.entry(target).or_insert((0, 0)), overlap));
+            keys = rotund.keys().copied().collect();
+            keys.sort_by_key(|&x| (Reverse(rotund[&x]), x.index()));
+        }
         keys
     }
 
+    fn rank(&self, n: usize, target: X) -> usize {
+        let rotund = self.make_rotund(n);
+        match rotund.iter().position(|&x| x == target) {
+            Some(r) => r,
+            None => rotund.len() + target.index() - rotund.iter().filter(|x| x.index() < target.index()).count()
+        }
+    }
+
+    fn symbol(&self, n: usize, rank: usize) -> X {
+        let rotund = self.make_rotund(n);
+        if rank < rotund.len() {
+            return rotund[rank]
+        }
+        let mut indices: Vec<usize> = rotund.iter().map(|x| x.index()).collect();
+        indices.sort();
+        let mut index = rank - rotund.len();
+        for i in indices {
+            if i > index { break }
+            index += 1;
+        }
+        X::from_index(index)
+    }
+
     fn add_to_cache(&mut self, n: usize) {
         let key = self.reversed[n];
-        let val_vec = self.cache.entry(key).or_insert(Vec::new());
-        val_vec.push(n)
+        let val_vec = self.cache.entry(key).or_default();
+        val_vec.push(n);
+
+        // the next context starts at n-1 and only sees positions up to n-1+window
+        if let Some(x) = n.checked_add(self.window).filter(|&x| x < self.reversed.len()) {
+            self.cache.get_mut(&self.reversed[x]).unwrap().pop_oldest();
+        }
     }
 }
 
@@ -69,52 +183,255 @@ fn bar(total_size: u64) -> ProgressBar { //from indicatif example "download.rs"
     pb
 }
 
-pub fn encode(reversed: Vec<u8>) -> Vec<u8> {
-    let nm_end = reversed.len();
-    let mut probcodes = vec![0u8; nm_end];
-    
-    let mut m = 0;
-    let mut n = nm_end - 1;
-    probcodes[m] = reversed[n];
+/// largest window (number of previous positions searched for matching contexts) for which encoding or decoding
+/// `nm_end` symbols with a reference of `reference_len` symbols stays below `budget` bytes.
+pub fn window_for_budget<X>(nm_end: usize, reference_len: usize, budget: usize) -> Option<usize> where X: Symbol {
+    // reversed input with reference, probcodes, and one partially filled chunk per distinct symbol
+    let distinct = X::ALPHABET.min(nm_end + reference_len);
+    let fixed = (2*nm_end + reference_len)*size_of::<X>() + distinct*CHUNK_LEN*size_of::<u32>();
+    budget.checked_sub(fixed).map(|free| free / size_of::<u32>())
+}
 
-    let mut helper = RotundHelper::new(reversed);
+pub fn encode<X>(reversed: Vec<X>) -> Vec<X> where X: Symbol {
+    encode_primed(reversed, &[], UNBOUNDED)
+}
 
-    let pb = bar(nm_end as u64);
-    loop {
-        let rotund = helper.make_rotund(n);
-        helper.add_to_cache(n);
-        n -= 1;
-        let target_u8 = helper.reversed[n];
-        m += 1;
-        probcodes[m] = rotund.iter().position(|&x| x == target_u8).unwrap() as u8;
-        if m % 32 == 0 { pb.set_position(m as u64); }
-        if n == 0 { pb.set_position(nm_end as u64); break }
+/// rank-encodes `reversed` with a model that has already seen `reference` (reversed as well), e.g. a
+/// training text or an older revision of the same file. decoding requires the same reference and window.
+pub fn encode_primed<X>(reversed: Vec<X>, reference: &[X], window: usize) -> Vec<X> where X: Symbol {
+    let pb = bar(reversed.len() as u64);
+    encode_block(reversed, reference, window, &pb)
+}
+
+/// `encode_primed` with the window stored in front of the probcodes as u64 (little endian), so that
+/// `decode_windowed` does not depend on getting the same window passed again
+pub fn encode_windowed<X>(reversed: Vec<X>, reference: &[X], window: usize) -> Vec<X> where X: Symbol {
+    let mut out = window_header(window);
+    out.extend(encode_primed(reversed, reference, window));
+    out
+}
+
+/// the window as u64, split into symbols (little endian)
+fn window_header<X>(window: usize) -> Vec<X> where X: Symbol {
+    let bits = 8*size_of::<X>();
+    (0..64/bits).map(|k| X::from_index((window as u64 >> (bits*k)) as usize & (X::ALPHABET-1))).collect()
+}
+
+/// the window stored by `window_header` at the start of `input`, and the symbols after it
+fn read_window<X>(input: &[X]) -> (usize, &[X]) where X: Symbol {
+    let bits = 8*size_of::<X>();
+    let (header, rest) = input.split_at(64/bits);
+    let window = header.iter().rev().fold(0u64, |w, x| w << bits | x.index() as u64);
+    (usize::try_from(window).unwrap_or(UNBOUNDED), rest)
+}
+
+fn encode_block<X>(reversed: Vec<X>, reference: &[X], window: usize, pb: &ProgressBar) -> Vec<X> where X: Symbol {
+    if reversed.len() < 2 { pb.inc(reversed.len() as u64); return reversed }
+    let mut state = EncodeState::new(reversed, reference, window);
+    state.run(usize::MAX, pb);
+    state.probcodes
+}
+
+/// position of a sequential encoding run, which can be written to and restored from a checkpoint file
+struct EncodeState<X> {
+    helper: RotundHelper<X>,
+    probcodes: Vec<X>,
+    m: usize,
+    n: usize
+}
+
+impl<X> EncodeState<X> where X: Symbol {
+    fn new(reversed: Vec<X>, reference: &[X], window: usize) -> Self {
+        let nm_end = reversed.len();
+        let n = nm_end - 1;
+        let probcodes = vec![reversed[n]; nm_end]; // probcodes[0] is the first symbol itself
+        Self { helper: RotundHelper::primed(reversed, reference, window), probcodes, m: 0, n }
+    }
+
+    /// encodes up to `steps` further symbols, returns true when the input is fully encoded
+    #[allow(clippy::manual_is_multiple_of)] // as in `decode_block`, `is_multiple_of` needs Rust 1.87
+    fn run(&mut self, steps: usize, pb: &ProgressBar) -> bool {
+        let nm_end = self.probcodes.len();
+        for _ in 0..steps {
+            if self.n == 0 { break }
+            let rank = self.helper.rank(self.n, self.helper.reversed[self.n-1]);
+            self.helper.add_to_cache(self.n);
+            self.n -= 1;
+            self.m += 1;
+            self.probcodes[self.m] = X::from_index(rank);
+            if self.m % 32 == 0 { pb.inc(32); }
+            if self.n == 0 { pb.inc((nm_end - self.m/32*32) as u64) }
+        }
+        self.n == 0
+    }
+}
+
+impl EncodeState<u8> {
+    /// checkpoint layout (little endian): m, n, the window, the input length and the length including a reference
+    /// as u64, the reversed input with reference, probcodes[..=m].
+    /// the position cache is not stored, as it follows from the reversed input and n.
+    fn save<P>(&self, path: P) -> io::Result<()> where P: AsRef<Path> {
+        let reversed = &self.helper.reversed;
+        let mut out: Vec<u8> = Vec::with_capacity(40 + reversed.len() + self.m + 1);
+        for x in [self.m, self.n, self.helper.window, self.probcodes.len(), reversed.len()] {
+            out.extend( (x as u64).to_le_bytes() );
+        }
+        out.extend(reversed);
+        out.extend(&self.probcodes[..=self.m]);
+
+        // write to a temporary file first, so that a crash while writing keeps the previous checkpoint intact
+        let tmp_path = path.as_ref().with_extension("tmp");
+        fs::write(&tmp_path, out)?;
+        fs::rename(tmp_path, path)
+    }
+
+    fn load<P>(path: P) -> io::Result<Self> where P: AsRef<Path> {
+        let input = fs::read(path)?;
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, format!("checkpoint {msg}"));
+        if input.len() < 40 {
+            return Err( invalid("is shorter than its header") )
+        }
+        let read_u64 = |pos: usize| u64::from_le_bytes(input[pos..pos+8].try_into().unwrap()) as usize;
+        let (m, n, window) = (read_u64(0), read_u64(8), read_u64(16));
+        let (nm_end, reversed_len) = (read_u64(24), read_u64(32));
+        let expected_len = reversed_len.checked_add(m).and_then(|x| x.checked_add(41));
+        if expected_len != Some(input.len()) {
+            return Err( invalid("has unexpected length") )
+        }
+        // without reference, n+m+1 symbols are always the whole input
+        if reversed_len != nm_end || n.checked_add(m+1) != Some(nm_end) {
+            return Err( invalid("has inconsistent positions") )
+        }
+
+        let mut helper = RotundHelper::new(input[40..40+reversed_len].to_vec(), window);
+        for x in (n+1..reversed_len).rev() {
+            helper.add_to_cache(x);
+        }
+        let mut probcodes = vec![0u8; nm_end];
+        probcodes[..=m].copy_from_slice(&input[40+reversed_len..]);
+        Ok( Self { helper, probcodes, m, n } )
     }
-    probcodes
+
+    fn run_checkpointed<P>(mut self, path: P, interval: usize) -> io::Result<Vec<u8>> where P: AsRef<Path> {
+        if interval == 0 {
+            return Err( io::Error::new(io::ErrorKind::InvalidInput, "checkpoint interval must be positive") )
+        }
+        let pb = bar(self.probcodes.len() as u64);
+        pb.set_position((self.m/32*32) as u64);
+        while !self.run(interval, &pb) {
+            self.save(&path)?;
+        }
+        if path.as_ref().exists() { fs::remove_file(path)?; }
+        let mut out = window_header(self.helper.window);
+        out.extend(self.probcodes);
+        Ok(out)
+    }
+}
+
+/// same output as `encode_windowed` without reference, but the encoder state is written to `path` every `interval` symbols.
+/// the checkpoint is removed once the encoding has finished.
+pub fn encode_checkpointed<P>(reversed: Vec<u8>, window: usize, path: P, interval: usize) -> io::Result<Vec<u8>> where P: AsRef<Path> {
+    if reversed.len() < 2 { return Ok(encode_windowed(reversed, &[], window)) }
+    EncodeState::new(reversed, &[], window).run_checkpointed(path, interval)
+}
+
+/// continues an interrupted `encode_checkpointed` run from its latest checkpoint
+pub fn resume<P>(path: P, interval: usize) -> io::Result<Vec<u8>> where P: AsRef<Path> {
+    EncodeState::load(&path)?.run_checkpointed(path, interval)
+}
+
+#[cfg(test)] // the modes decode with the window stored by `encode_windowed`
+pub fn decode<X>(probcodes: &[X]) -> Vec<X> where X: Symbol {
+    decode_primed(probcodes, &[], UNBOUNDED)
+}
+
+/// inverse of `encode_primed`, `reference` and `window` have to be identical to the ones used for encoding
+pub fn decode_primed<X>(probcodes: &[X], reference: &[X], window: usize) -> Vec<X> where X: Symbol {
+    let pb = bar(probcodes.len() as u64);
+    decode_block(probcodes, reference, window, &pb)
+}
+
+/// inverse of `encode_windowed`, `reference` has to be identical to the one used for encoding
+pub fn decode_windowed<X>(input: &[X], reference: &[X]) -> Vec<X> where X: Symbol {
+    let (window, probcodes) = read_window(input);
+    decode_primed(probcodes, reference, window)
 }
 
-pub fn decode(probcodes: &[u8]) -> Vec<u8> {
+fn decode_block<X>(probcodes: &[X], reference: &[X], window: usize, pb: &ProgressBar) -> Vec<X> where X: Symbol {
     let nm_end = probcodes.len();
-    let mut helper = RotundHelper::new(vec![0u8; nm_end]);
+    if nm_end < 2 { pb.inc(nm_end as u64); return probcodes.to_vec() }
+    let mut helper = RotundHelper::primed(vec![probcodes[0]; nm_end], reference, window);
 
     let mut m = 0;
     let mut n = nm_end - 1;
     helper.reversed[n] = probcodes[m];
 
-    let pb = bar(nm_end as u64);
     loop {
-        let rotund = helper.make_rotund(n);
-        helper.add_to_cache(n);
         m += 1;
-        let ch = rotund[probcodes[m] as usize];
+        let ch = helper.symbol(n, probcodes[m].index());
+        helper.add_to_cache(n);
         n -= 1;
         helper.reversed[n] = ch;
-        if m % 32 == 0 { pb.set_position(m as u64); }
-        if n == 0 { pb.set_position(nm_end as u64); break }
+        if m % 32 == 0 { pb.inc(32); }
+        if n == 0 { pb.inc((nm_end - m/32*32) as u64); break }
     }
+    helper.reversed.truncate(nm_end);
     helper.reversed
 }
 
+/// runs `work` on every block, distributing the blocks round-robin over all available cores.
+fn map_blocks<X,F>(blocks: Vec<&[X]>, work: F) -> Vec<Vec<X>> where X: Symbol, F: Fn(&[X]) -> Vec<X> + Sync {
+    let threads = thread::available_parallelism().map_or(1, |n| n.get()).min(blocks.len().max(1));
+    let mut results: Vec<Vec<X>> = vec![Vec::new(); blocks.len()];
+    thread::scope(|s| {
+        let handles: Vec<_> = (0..threads).map(|t| {
+            let (blocks, work) = (&blocks, &work);
+            s.spawn(move || {
+                blocks.iter().enumerate().skip(t).step_by(threads).map(|(b, block)| (b, work(block))).collect::<Vec<_>>()
+            })
+        }).collect();
+        for handle in handles {
+            for (b, out) in handle.join().unwrap() { results[b] = out; }
+        }
+    });
+    results
+}
+
+/// block-parallel variant of `encode`. every block of `block_size` symbols is rank-encoded independently
+/// of all others on its own thread. the output starts with a block index (u32 block count followed by
+/// one u32 length per block, all little endian), then the probcodes of all blocks are concatenated.
+pub fn encode_blocks(reversed: Vec<u8>, block_size: usize) -> Vec<u8> {
+    let pb = bar(reversed.len() as u64);
+    let blocks: Vec<&[u8]> = reversed.chunks(block_size).collect();
+
+    let mut out: Vec<u8> = Vec::with_capacity(reversed.len() + 4*(blocks.len()+1));
+    out.extend( (blocks.len() as u32).to_le_bytes() );
+    for block in &blocks {
+        out.extend( (block.len() as u32).to_le_bytes() );
+    }
+    for probcodes in map_blocks(blocks, |block| encode_block(block.to_vec(), &[], UNBOUNDED, &pb)) {
+        out.extend(probcodes);
+    }
+    out
+}
+
+/// inverse of `encode_blocks`
+pub fn decode_blocks(input: &[u8]) -> Vec<u8> {
+    let read_u32 = |pos: usize| u32::from_le_bytes([input[pos], input[pos+1], input[pos+2], input[pos+3]]) as usize;
+    let block_count = read_u32(0);
+    let mut pos = 4*(block_count+1);
+
+    let mut blocks: Vec<&[u8]> = Vec::with_capacity(block_count);
+    for b in 0..block_count {
+        let len = read_u32(4*(b+1));
+        blocks.push( &input[pos..pos+len] );
+        pos += len;
+    }
+
+    let pb = bar((pos - 4*(block_count+1)) as u64);
+    map_blocks(blocks, |block| decode_block(block, &[], UNBOUNDED, &pb)).concat()
+}
+
 #[test]
 pub fn encode_decode() {
     let input = b"This is a simple text for encoding this and that information.".to_vec();
@@ -126,4 +443,98 @@ pub fn encode_decode() {
     let mut output = decode(&encoded);
     output.reverse();
     assert_eq!(String::from_utf8(input).unwrap(), String::from_utf8(output).unwrap())
+}
+
+#[test]
+pub fn encode_decode_blocks() {
+    let input = b"This is a simple text for encoding this and that information, cut into several blocks.".to_vec();
+    let mut reversed = input.clone();
+    reversed.reverse();
+    for block_size in [1, 7, 32, input.len()] {
+        let encoded = encode_blocks(reversed.clone(), block_size);
+        let mut output = decode_blocks(&encoded);
+        output.reverse();
+        assert_eq!(String::from_utf8_lossy(&input), String::from_utf8_lossy(&output));
+    }
+    // a single block is identical to the sequential encoding after the 8-byte block index
+    assert_eq!(encode_blocks(reversed.clone(), input.len())[8..], encode(reversed));
+}
+
+#[test]
+pub fn checkpoint_resume() {
+    let input = b"This is a simple text for encoding this and that information, interrupted in between.".to_vec();
+    let mut reversed = input.clone();
+    reversed.reverse();
+    let path = std::env::temp_dir().join("rfg_checkpoint_resume.ckpt");
+
+    let mut state = EncodeState::new(reversed.clone(), &[], 16);
+    state.run(20, &ProgressBar::hidden());
+    state.save(&path).unwrap();
+    drop(state); // "crash"
+    let checkpoint = fs::read(&path).unwrap();
+
+    let resumed = resume(&path, 16).unwrap();
+    assert!(!path.exists());
+    assert_eq!(resumed, encode_windowed(reversed.clone(), &[], 16));
+    let mut output = decode_windowed(&resumed, &[]);
+    output.reverse();
+    assert_eq!(output, input);
+
+    // truncated or garbage checkpoints are rejected instead of panicking, as is an interval of 0
+    let mut shifted = checkpoint.clone();
+    shifted[8] += 5; // n no longer matches m
+    for garbage in [vec![1u8; 20], vec![0xFF; 48], shifted] {
+        fs::write(&path, garbage).unwrap();
+        assert_eq!(resume(&path, 16).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+    fs::write(&path, checkpoint).unwrap();
+    assert_eq!(resume(&path, 0).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(encode_checkpointed(reversed, 16, &path, 0).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+pub fn encode_decode_u16() {
+    // word tokens beyond the u8 range, including a symbol that never occurs as a successor
+    let input: Vec<u16> = vec![300, 7, 65535, 300, 7, 65535, 300, 7, 12, 1000, 300, 7, 12, 40000, 7];
+    let mut reversed = input.clone();
+    reversed.reverse();
+    let encoded = encode(reversed.clone());
+    let mut output = decode(&encoded);
+    output.reverse();
+    assert_eq!(input, output);
+
+    let windowed = encode_windowed(reversed.clone(), &[], 3);
+    assert_eq!(windowed[..4], [3, 0, 0, 0]);
+    assert_eq!(decode_windowed(&windowed, &[]), reversed);
+}
+
+#[test]
+pub fn encode_decode_primed() {
+    let reference = b"This is a simple text for encoding this and that information.".to_vec();
+    let input = b"This is a simple text for encoding those and that informations.".to_vec();
+    let reversed_reference: Vec<u8> = reference.into_iter().rev().collect();
+    let reversed: Vec<u8> = input.iter().copied().rev().collect();
+
+    let primed = encode_primed(reversed.clone(), &reversed_reference, UNBOUNDED);
+    let zeros = |codes: &[u8]| codes.iter().filter(|&&x| x == 0).count();
+    assert!(zeros(&primed) > zeros(&encode(reversed)) + 40);
+
+    let mut output = decode_primed(&primed, &reversed_reference, UNBOUNDED);
+    output.reverse();
+    assert_eq!(String::from_utf8(input).unwrap(), String::from_utf8(output).unwrap());
+}
+
+#[test]
+pub fn encode_decode_window() {
+    let input = b"This is a simple text for encoding this and that information with a limited memory.".to_vec();
+    let reversed: Vec<u8> = input.iter().copied().rev().collect();
+    assert_eq!(encode_primed(reversed.clone(), &[], input.len()), encode(reversed.clone()));
+
+    for window in [0, 1, 5, 30, UNBOUNDED] {
+        let encoded = encode_windowed(reversed.clone(), &[], window);
+        let mut output = decode_windowed(&encoded, &[]);
+        output.reverse();
+        assert_eq!(String::from_utf8_lossy(&input), String::from_utf8_lossy(&output));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,104 @@
+use std::ops::Range;
+use crate::varint;
+
+const SHINGLE_LEN: usize = 8;
+
+pub enum Order {
+    /// by title, so that pages with a common title prefix come together
+    Title,
+    /// by the smallest hash of the text shingles, so that pages sharing content tend to come together
+    Shingles
+}
+
+fn find(input: &[u8], pattern: &[u8], from: usize) -> Option<usize> {
+    input[from..].windows(pattern.len()).position(|w| w == pattern).map(|k| from+k)
+}
+
+/// byte ranges of the complete pages, each from `<page>` up to and including the next `</page>`.
+/// the bytes between pages stay in place when pages are reordered, an incomplete last page as well.
+fn pages(input: &[u8]) -> Vec<Range<usize>> {
+    let mut pages = Vec::new();
+    let mut n = 0;
+    while let Some(start) = find(input, b"<page>", n) {
+        let Some(end) = find(input, b"</page>", start) else { break };
+        n = end + b"</page>".len();
+        pages.push(start..n);
+    }
+    pages
+}
+
+fn title(page: &[u8]) -> &[u8] {
+    let start = find(page, b"<title>", 0).map_or(page.len(), |k| k + b"<title>".len());
+    let end = find(page, b"</title>", start).unwrap_or(start);
+    &page[start..end]
+}
+
+fn min_shingle(page: &[u8]) -> u64 {
+    let text = &page[find(page, b"<text", 0).unwrap_or(0)..];
+    text.windows(SHINGLE_LEN)
+        .map(|w| u64::from_le_bytes(w.try_into().unwrap()).wrapping_mul(0x9E3779B97F4A7C15).rotate_left(29).wrapping_mul(0xBF58476D1CE4E5B9))
+        .min().unwrap_or(u64::MAX)
+}
+
+/// writes the concatenation of the pieces between and including the pages, page k replaced by page `order[k]`
+fn arrange(input: &[u8], pages: &[Range<usize>], order: &[usize]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len());
+    out.extend(&input[..pages.first().map_or(input.len(), |page| page.start)]);
+    for (k, page) in pages.iter().enumerate() {
+        out.extend(&input[pages[order[k]].clone()]);
+        out.extend(&input[page.end..pages.get(k+1).map_or(input.len(), |next| next.start)]);
+    }
+    out
+}
+
+/// reorders the pages of a dump by `order`. the permutation (original index of each output page) is
+/// stored as zigzag varints of the differences between neighbouring entries.
+pub fn encode(input: &[u8], order: Order) -> (Vec<u8>, Vec<u8>) {
+    let pages = pages(input);
+    let mut permutation: Vec<usize> = (0..pages.len()).collect();
+    match order {
+        Order::Title => permutation.sort_by_key(|&k| title(&input[pages[k].clone()])),
+        Order::Shingles => permutation.sort_by_key(|&k| min_shingle(&input[pages[k].clone()]))
+    }
+
+    let mut permutation_bytes = Vec::new();
+    let mut previous = 0;
+    for &k in &permutation {
+        varint::write(&mut permutation_bytes, varint::zigzag(k as i64 - previous));
+        previous = k as i64;
+    }
+    (arrange(input, &pages, &permutation), permutation_bytes)
+}
+
+pub fn decode(input: &[u8], permutation_bytes: &[u8]) -> Vec<u8> {
+    let pages = pages(input);
+    let mut inverse = vec![0; pages.len()];
+    let (mut pos, mut previous) = (0, 0);
+    for slot in 0..pages.len() {
+        previous += varint::unzigzag(varint::read(permutation_bytes, &mut pos));
+        inverse[previous as usize] = slot;
+    }
+    arrange(input, &pages, &inverse)
+}
+
+#[test]
+fn encode_decode() {
+    use crate::fixtures::page;
+    let input = [
+        "<mediawiki>\n".to_string(), page("Berlin", 1, "capital of Germany"), page("Anarchism", 2, "political philosophy"),
+        page("Bern", 3, "capital of Switzerland"), page("Anarchy", 4, "political philosophy and more"),
+        "</mediawiki>\n  <page><title>Abc</title>".to_string()
+    ].concat();
+
+    let (reordered, permutation) = encode(input.as_bytes(), Order::Title);
+    let titles: Vec<&[u8]> = pages(&reordered).into_iter().map(|page| title(&reordered[page])).collect();
+    assert_eq!(titles, [&b"Anarchism"[..], b"Anarchy", b"Berlin", b"Bern"]);
+    assert_eq!(permutation, [2, 4, 5, 4]);
+    assert!(reordered.ends_with(b"</mediawiki>\n  <page><title>Abc</title>"));
+    assert_eq!(decode(&reordered, &permutation), input.as_bytes());
+
+    let (reordered, permutation) = encode(input.as_bytes(), Order::Shingles);
+    assert_eq!(reordered.len(), input.len());
+    assert_eq!(decode(&reordered, &permutation), input.as_bytes());
+    assert_eq!(decode(b"", &[]), b"");
+}
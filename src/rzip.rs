@@ -0,0 +1,98 @@
+use crate::varint;
+
+/// length of the hashed blocks. blocks are indexed every `BLOCK` positions, so every repeat of at least
+/// 2*`BLOCK`-1 bytes covers an indexed block of its earlier occurrence.
+const BLOCK: usize = 16;
+const MIN_REPEAT: usize = 32;
+const HASH_BITS: u32 = 24;
+const BASE: u64 = 0x100000001b3;
+
+/// the input without its long repeats, and per repeat the varints (literals before it, distance, length)
+pub struct Streams {
+    pub literals: Vec<u8>,
+    pub refs: Vec<u8>
+}
+
+fn block_hash(block: &[u8]) -> u64 {
+    block.iter().fold(0u64, |h, &c| h.wrapping_mul(BASE).wrapping_add(c as u64))
+}
+
+fn slot(hash: u64, bits: u32) -> usize {
+    (hash.wrapping_mul(0x9E3779B97F4A7C15) >> (64 - bits)) as usize
+}
+
+/// rzip-style long-range repeat elimination over the whole input: a rolling hash of the `BLOCK` bytes at
+/// every position is looked up among the indexed blocks, and confirmed matches are extended in both
+/// directions. repeats of at least `MIN_REPEAT` bytes move to `refs`, everything else stays in `literals`.
+pub fn encode(input: &[u8]) -> Streams {
+    let mut streams = Streams { literals: Vec::with_capacity(input.len()), refs: Vec::new() };
+    let bits = HASH_BITS.min((input.len() / BLOCK).max(1).ilog2() + 1); // about one slot per indexed block
+    let mut table = vec![usize::MAX; 1 << bits];
+    let top_power = (1..BLOCK).fold(1u64, |p, _| p.wrapping_mul(BASE)); // weight of the byte leaving the block
+
+    let mut literal_start = 0;
+    let mut i = 0;
+    let mut hash = input.get(..BLOCK).map_or(0, block_hash);
+    while i + BLOCK <= input.len() {
+        let j = table[slot(hash, bits)];
+        if j != usize::MAX && input[j..j+BLOCK] == input[i..i+BLOCK] {
+            let back = input[literal_start..i].iter().rev().zip(input[..j].iter().rev()).take_while(|(a, b)| a == b).count();
+            let forward = input[i+BLOCK..].iter().zip(&input[j+BLOCK..]).take_while(|(a, b)| a == b).count();
+            let (start, len) = (i-back, back+BLOCK+forward);
+            if len >= MIN_REPEAT {
+                streams.literals.extend(&input[literal_start..start]);
+                for x in [start-literal_start, i-j, len] {
+                    varint::write(&mut streams.refs, x as u64);
+                }
+                i = start+len;
+                literal_start = i;
+                hash = input.get(i..i+BLOCK).map_or(0, block_hash);
+                continue
+            }
+        }
+        if i % BLOCK == 0 {
+            table[slot(hash, bits)] = i;
+        }
+        if i + BLOCK < input.len() {
+            hash = hash.wrapping_sub((input[i] as u64).wrapping_mul(top_power)).wrapping_mul(BASE).wrapping_add(input[i+BLOCK] as u64);
+        }
+        i += 1;
+    }
+    streams.literals.extend(&input[literal_start..]);
+    streams
+}
+
+pub fn decode(streams: &Streams) -> Vec<u8> {
+    let mut out = Vec::with_capacity(2*streams.literals.len());
+    let (mut r, mut l) = (0, 0);
+    while r < streams.refs.len() {
+        let run = varint::read(&streams.refs, &mut r) as usize;
+        let distance = varint::read(&streams.refs, &mut r) as usize;
+        let len = varint::read(&streams.refs, &mut r) as usize;
+        out.extend(&streams.literals[l..l+run]);
+        l += run;
+        let start = out.len() - distance;
+        for k in 0..len {
+            out.push(out[start+k]);
+        }
+    }
+    out.extend(&streams.literals[l..]);
+    out
+}
+
+#[test]
+fn encode_decode() {
+    let article = crate::fixtures::ARTICLE.as_bytes();
+    let mut input: Vec<u8> = (0..5000u32).map(|x| (x.wrapping_mul(2654435761) >> 13) as u8).collect();
+    input.extend(article);
+    input.extend(&input[1000..3000].to_vec());
+    input.extend([b'x'; 100]);
+    input.extend(b"Theory: ");
+    input.extend(article);
+    input.extend(&article[..20]);
+    let streams = encode(&input);
+    assert!(streams.literals.len() < input.len() - 2000 - article.len());
+    assert!(streams.literals.ends_with(b"xTheory: '''Anarchism''' is a"));
+    assert_eq!(decode(&streams), input);
+    assert_eq!(decode(&encode(b"short")), b"short");
+}
@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+use crate::varint;
+
+/// tag codes in `structure`: the predicted tag, a new tag from the `tags` stream, or 2+k for the k-th known tag
+const PREDICTED: u64 = 0;
+const NEW_TAG: u64 = 1;
+/// text flags in `structure`: the same text as last time in this context, or the next text of the `text` stream
+const SAME_TEXT: u8 = 0;
+const NEW_TEXT: u8 = 1;
+
+const NONE: usize = usize::MAX;
+
+/// the XML split into markup and text: `structure` holds a text flag, then a tag code and a text flag per tag.
+/// `tags` holds the new tags (varint length, bytes), `text` the new texts, each terminated by '<'.
+pub struct Skeleton {
+    pub structure: Vec<u8>,
+    pub tags: Vec<u8>,
+    pub text: Vec<u8>
+}
+
+fn tag_name(tag: &[u8]) -> &[u8] {
+    let name = tag.strip_prefix(b"</").or(tag.strip_prefix(b"<")).unwrap_or(tag);
+    let len = name.iter().take_while(|&&c| !c.is_ascii_whitespace() && c != b'/' && c != b'>').count();
+    &name[..len]
+}
+
+/// tag dictionary, stack of open elements and the predictions for tags and texts. both directions update
+/// it with the same tags and texts, so that they predict alike. the context of a prediction is the
+/// innermost open element and the previous tag.
+#[derive(Default)]
+struct Schema {
+    ids: HashMap<Vec<u8>, usize>,
+    tags: Vec<Vec<u8>>,
+    open: Vec<usize>,
+    previous: Option<usize>,
+    next_tags: HashMap<(usize, usize), usize>,
+    last_texts: HashMap<(usize, usize), Vec<u8>>
+}
+
+impl Schema {
+    fn context(&self) -> (usize, usize) {
+        (self.open.last().copied().unwrap_or(NONE), self.previous.unwrap_or(NONE))
+    }
+
+    fn predicted(&self) -> Option<usize> {
+        self.next_tags.get(&self.context()).copied()
+    }
+
+    fn last_text(&self) -> &[u8] {
+        self.last_texts.get(&self.context()).map_or(&[], |text| &text[..])
+    }
+
+    fn id(&mut self, tag: &[u8]) -> usize {
+        if let Some(&id) = self.ids.get(tag) { return id }
+        self.ids.insert(tag.to_vec(), self.tags.len());
+        self.tags.push(tag.to_vec());
+        self.tags.len()-1
+    }
+
+    fn add_tag(&mut self, id: usize) {
+        self.next_tags.insert(self.context(), id);
+        let tag = &self.tags[id];
+        if tag.starts_with(b"</") {
+            if self.open.last().is_some_and(|&top| tag_name(&self.tags[top]) == tag_name(tag)) {
+                self.open.pop();
+            }
+        } else if tag.get(1).is_some_and(|c| c.is_ascii_alphabetic() || *c == b'_' || *c == b':' || *c >= 0x80) && !tag.ends_with(b"/>") {
+            self.open.push(id);
+        }
+        self.previous = Some(id);
+    }
+
+    fn add_text(&mut self, text: &[u8]) {
+        if self.last_text() != text {
+            self.last_texts.insert(self.context(), text.to_vec());
+        }
+    }
+}
+
+/// length of the tag (from '<' up to and including the next '>') or text (up to the next '<') at the start of `input`
+fn segment_len(input: &[u8]) -> usize {
+    match input.first() {
+        Some(b'<') => input.iter().position(|&c| c == b'>').map_or(input.len(), |k| k+1),
+        _ => input.iter().position(|&c| c == b'<').unwrap_or(input.len())
+    }
+}
+
+pub fn encode(input: &[u8]) -> Skeleton {
+    let mut skeleton = Skeleton { structure: Vec::new(), tags: Vec::new(), text: Vec::new() };
+    let mut schema = Schema::default();
+    let mut n = 0;
+    loop {
+        let len = segment_len(&input[n..]);
+        let text = if input[n..].starts_with(b"<") { &[] } else { &input[n..n+len] };
+        if schema.last_text() == text {
+            skeleton.structure.push(SAME_TEXT);
+        } else {
+            skeleton.structure.push(NEW_TEXT);
+            skeleton.text.extend(text);
+            skeleton.text.push(b'<');
+        }
+        schema.add_text(text);
+        n += text.len();
+        if n == input.len() { break }
+
+        let tag = &input[n..n+segment_len(&input[n..])];
+        let code = match (schema.ids.get(tag).copied(), schema.predicted()) {
+            (Some(id), Some(predicted)) if id == predicted => PREDICTED,
+            (Some(id), _) => 2 + id as u64,
+            (None, _) => {
+                varint::write(&mut skeleton.tags, tag.len() as u64);
+                skeleton.tags.extend(tag);
+                NEW_TAG
+            }
+        };
+        varint::write(&mut skeleton.structure, code);
+        let id = schema.id(tag);
+        schema.add_tag(id);
+        n += tag.len();
+    }
+    skeleton
+}
+
+pub fn decode(skeleton: &Skeleton) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut schema = Schema::default();
+    let (mut s, mut t, mut x) = (0, 0, 0);
+    loop {
+        let text = match skeleton.structure[s] {
+            SAME_TEXT => schema.last_text().to_vec(),
+            _ => {
+                let len = skeleton.text[x..].iter().position(|&c| c == b'<').unwrap();
+                x += len+1;
+                skeleton.text[x-len-1..x-1].to_vec()
+            }
+        };
+        s += 1;
+        out.extend(&text);
+        schema.add_text(&text);
+        if s == skeleton.structure.len() { break }
+
+        let id = match varint::read(&skeleton.structure, &mut s) {
+            PREDICTED => schema.predicted().unwrap(),
+            NEW_TAG => {
+                let len = varint::read(&skeleton.tags, &mut t) as usize;
+                t += len;
+                schema.id(&skeleton.tags[t-len..t])
+            }
+            code => code as usize - 2
+        };
+        out.extend(&schema.tags[id]);
+        schema.add_tag(id);
+    }
+    out
+}
+
+#[test]
+fn encode_decode() {
+    let page = |title: &str, id: usize| crate::fixtures::page(title, id, "a &lt; b");
+    let input = ["<mediawiki>\n".to_string(), page("A", 1), page("B", 2), page("C", 3), "<!-- a > b --><x/><y attr='>'>".to_string(), page("D", 4), "</mediawiki>\n<unterminated".to_string()].concat();
+    let skeleton = encode(input.as_bytes());
+    assert!(skeleton.text.windows(14).any(|w| w == b"B<2<20<C<3<30<"));
+    assert!(skeleton.structure.iter().filter(|&&c| c != 0).count() < skeleton.structure.len() / 2);
+    assert_eq!(decode(&skeleton), input.as_bytes());
+    assert_eq!(decode(&encode(b"")), b"");
+}
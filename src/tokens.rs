@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+
+/// token ids below 256 are the bytes themselves, words of the vocabulary follow
+const FIRST_WORD: usize = 256;
+
+/// words are maximal runs of ASCII alphanumerics and non-ASCII (UTF-8) bytes
+fn is_word_byte(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c >= 0x80
+}
+
+fn words(input: &[u8]) -> impl Iterator<Item = &[u8]> {
+    input.split(|&c| !is_word_byte(c)).filter(|word| !word.is_empty())
+}
+
+/// most frequent words with at least two bytes that occur at least twice, most frequent first
+pub fn build_vocabulary(input: &[u8], max_words: usize) -> Vec<Vec<u8>> {
+    let mut counts: HashMap<&[u8], usize> = HashMap::new();
+    for word in words(input) {
+        *counts.entry(word).or_default() += 1;
+    }
+    let mut vocabulary: Vec<(&[u8], usize)> = counts.into_iter().filter(|&(word, count)| word.len() >= 2 && count >= 2).collect();
+    vocabulary.sort_by_key(|&(word, count)| (std::cmp::Reverse(count), word));
+    vocabulary.truncate(max_words.min(u16::MAX as usize + 1 - FIRST_WORD));
+    vocabulary.into_iter().map(|(word, _)| word.to_vec()).collect()
+}
+
+/// maps every word of `vocabulary` to its u16 token, all other bytes (punctuation, whitespace,
+/// words outside the vocabulary) become a token of their byte value
+pub fn tokenize(input: &[u8], vocabulary: &[Vec<u8>]) -> Vec<u16> {
+    let ids: HashMap<&[u8], u16> = vocabulary.iter().enumerate().map(|(k, word)| (&word[..], (FIRST_WORD + k) as u16)).collect();
+    let mut tokens = Vec::with_capacity(input.len());
+    let mut n = 0;
+    while n < input.len() {
+        let len = input[n..].iter().take_while(|&&c| is_word_byte(c)).count();
+        match ids.get(&input[n..n+len]) {
+            Some(&id) if len > 0 => { tokens.push(id); n += len; }
+            _ => {
+                tokens.extend( input[n..n+len.max(1)].iter().map(|&c| c as u16) );
+                n += len.max(1);
+            }
+        }
+    }
+    tokens
+}
+
+pub fn detokenize(tokens: &[u16], vocabulary: &[Vec<u8>]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(4*tokens.len());
+    for &token in tokens {
+        match (token as usize).checked_sub(FIRST_WORD) {
+            Some(k) => out.extend(&vocabulary[k]),
+            None => out.push(token as u8)
+        }
+    }
+    out
+}
+
+/// one word per line, words never contain a newline
+pub fn vocabulary_to_bytes(vocabulary: &[Vec<u8>]) -> Vec<u8> {
+    vocabulary.join(&b'\n')
+}
+
+pub fn vocabulary_from_bytes(bytes: &[u8]) -> Vec<Vec<u8>> {
+    if bytes.is_empty() { return Vec::new() }
+    bytes.split(|&c| c == b'\n').map(|word| word.to_vec()).collect()
+}
+
+#[test]
+fn tokenize_detokenize() {
+    let input = "the page of the title, and the other page; the ünïcode wörds and wörds 1999 or 1999.".as_bytes();
+    let vocabulary = build_vocabulary(input, 1000);
+    assert_eq!(vocabulary[0], b"the");
+    assert!(vocabulary.contains(&"wörds".as_bytes().to_vec()));
+
+    let tokens = tokenize(input, &vocabulary);
+    assert_eq!(tokens[0], FIRST_WORD as u16);
+    assert!(tokens.len() < 2*input.len()/3);
+    assert_eq!(vocabulary_from_bytes(&vocabulary_to_bytes(&vocabulary)), vocabulary);
+    assert_eq!(detokenize(&tokens, &vocabulary), input);
+}
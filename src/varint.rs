@@ -0,0 +1,43 @@
+/// LEB128: 7 bits per byte, least significant first, the high bit marks a following byte
+pub fn write(out: &mut Vec<u8>, mut x: u64) {
+    while x >= 0x80 {
+        out.push(x as u8 | 0x80);
+        x >>= 7;
+    }
+    out.push(x as u8);
+}
+
+/// reads a varint at `*pos` and advances `*pos` behind it
+pub fn read(input: &[u8], pos: &mut usize) -> u64 {
+    let mut x = 0u64;
+    let mut shift = 0;
+    loop {
+        let byte = input[*pos];
+        *pos += 1;
+        x |= ((byte & 0x7F) as u64) << shift;
+        if byte < 0x80 { return x }
+        shift += 7;
+    }
+}
+
+/// maps signed values to unsigned ones with small magnitudes staying small: 0, -1, 1, -2, ...
+pub fn zigzag(x: i64) -> u64 {
+    ((x << 1) ^ (x >> 63)) as u64
+}
+
+pub fn unzigzag(x: u64) -> i64 {
+    (x >> 1) as i64 ^ -((x & 1) as i64)
+}
+
+#[test]
+fn write_read() {
+    let values = [0u64, 1, 127, 128, 300, u32::MAX as u64, u64::MAX];
+    let mut out = Vec::new();
+    values.iter().for_each(|&x| write(&mut out, x));
+    assert_eq!(&out[..4], [0, 1, 127, 0x80]);
+    let mut pos = 0;
+    assert!(values.iter().all(|&x| read(&out, &mut pos) == x));
+    assert_eq!(pos, out.len());
+    assert!([0i64, -1, 1, i64::MIN, i64::MAX].iter().all(|&x| unzigzag(zigzag(x)) == x));
+    assert_eq!(zigzag(-1), 1);
+}
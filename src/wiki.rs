@@ -0,0 +1,88 @@
+use crate::prep::{push_escaped, read_controls, write_controls};
+
+/// MediaWiki markup replaced by a single control symbol each, longer tokens before their prefixes
+const MARKUP: [&[u8]; 16] = [
+    b"'''", b"''", b"[[", b"]]", b"{{", b"}}", b"===", b"==",
+    b"\n*", b"\n#", b"\n:", b"{|", b"|}", b"\n|-", b"\n|", b"||"
+];
+const LINK_OPEN: usize = 2;
+const LINK_CLOSE: usize = 3;
+
+/// number of control symbols `encode` needs: escape, link with label equal to target and one per markup token
+pub const CONTROL_COUNT: usize = 2 + MARKUP.len();
+
+/// length of the target of a `[[target|target]]` link at the start of `input`. the target must not contain
+/// link syntax, newlines or control symbols, so that it can be copied verbatim.
+fn same_label_link(input: &[u8], control_chars: &[u8]) -> Option<usize> {
+    let rest = input.strip_prefix(MARKUP[LINK_OPEN])?;
+    let len = rest.iter().position(|c| b"[]|\n".contains(c) || control_chars.contains(c))?;
+    let label = rest[len..].strip_prefix(b"|")?.get(..len)?;
+    (len > 0 && label == &rest[..len] && rest[2*len+1..].starts_with(MARKUP[LINK_CLOSE])).then_some(len)
+}
+
+/// replaces the `MARKUP` tokens by the symbols `control_chars[2..]`. a link whose label equals its target
+/// is written as `control_chars[1]`, the target and the link closing symbol.
+pub fn encode(input: &[u8], control_chars: &[u8]) -> Vec<u8> {
+    let same_link = control_chars[1];
+    let markup = &control_chars[2..];
+
+    let mut out: Vec<u8> = Vec::with_capacity(input.len());
+    write_controls(&mut out, control_chars);
+
+    let mut n = 0;
+    while n < input.len() {
+        if let Some(len) = same_label_link(&input[n..], control_chars) {
+            out.push(same_link);
+            out.extend(&input[n+2..n+2+len]);
+            out.push(markup[LINK_CLOSE]);
+            n += 2*len + 5;
+            continue
+        }
+        if let Some(k) = MARKUP.iter().position(|token| input[n..].starts_with(token)) {
+            out.push(markup[k]);
+            n += MARKUP[k].len();
+            continue
+        }
+        push_escaped(&mut out, &input[n..n+1], control_chars);
+        n += 1;
+    }
+    out
+}
+
+pub fn decode(input: &[u8]) -> Vec<u8> {
+    let (control_chars, mut n) = read_controls(input);
+    let escape = control_chars[0];
+    let same_link = control_chars[1];
+    let markup = &control_chars[2..];
+
+    let mut out: Vec<u8> = Vec::with_capacity(2*input.len());
+    while n < input.len() {
+        let ch = input[n];
+        if ch == escape {
+            n += 1;
+            out.push(input[n]);
+        } else if ch == same_link {
+            let len = input[n+1..].iter().position(|&c| c == markup[LINK_CLOSE]).unwrap();
+            let target = &input[n+1..n+1+len];
+            out.extend([MARKUP[LINK_OPEN], target, b"|", target, MARKUP[LINK_CLOSE]].concat());
+            n += len+1;
+        } else if let Some(k) = markup.iter().position(|&c| c == ch) {
+            out.extend(MARKUP[k]);
+        } else {
+            out.push(ch);
+        }
+        n += 1;
+    }
+    out
+}
+
+#[test]
+fn encode_decode() {
+    let control_chars: Vec<u8> = (1..=CONTROL_COUNT as u8).collect();
+    let input = b"{{Infobox|name=X}}\n'''Foo''' is a [[bar]], [[Baz|Baz]] or [[Baz|baz]] ''it'' [[a|a]b]]\n==History==\n* one\n# two\n{| class=x\n|-\n| a || b\n|}\n[[|]] [[x\x01|x\x01]] \x01".to_vec();
+    let encoded = encode(&input, &control_chars);
+    assert_eq!(&encoded[..3], [CONTROL_COUNT as u8, 1, 2]);
+    assert!(encoded.windows(5).any(|w| w == b"\x02Baz\x06"));
+    assert!(encoded.len() - 1 - CONTROL_COUNT < input.len() - 20);
+    assert_eq!(decode(&encoded), input);
+}
@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+use crate::prep::{push_escaped, read_controls, write_controls};
+
+const MIN_WORD_LEN: usize = 3;
+
+/// words are maximal runs of ASCII letters
+fn words(input: &[u8]) -> impl Iterator<Item = &[u8]> {
+    input.split(|c| !c.is_ascii_alphabetic()).filter(|word| word.len() >= MIN_WORD_LEN)
+}
+
+/// dictionary of the words with the largest savings in `sample`, one word per line
+pub fn build_dictionary(sample: &[u8], max_words: usize) -> Vec<u8> {
+    let mut counts: HashMap<&[u8], usize> = HashMap::new();
+    for word in words(sample) {
+        *counts.entry(word).or_default() += 1;
+    }
+    let mut dictionary: Vec<(&[u8], usize)> = counts.into_iter().filter(|&(_, count)| count >= 2).collect();
+    // each occurrence is replaced by a 2-byte codeword
+    dictionary.sort_by_key(|&(word, count)| (std::cmp::Reverse(count*(word.len()-2)), word));
+    dictionary.truncate(max_words);
+    dictionary.into_iter().map(|(word, _)| word).collect::<Vec<&[u8]>>().join(&b'\n')
+}
+
+/// number of control symbols `encode` uses for a dictionary file: one escape and one prefix per 256 words.
+/// the count is stored in a single byte, which limits the dictionary to 254*256 words.
+pub fn control_count(dictionary: &[u8]) -> usize {
+    (1 + parse_dictionary(dictionary).len().div_ceil(256)).min(255)
+}
+
+fn parse_dictionary(dictionary: &[u8]) -> Vec<&[u8]> {
+    dictionary.split(|&c| c == b'\n').filter(|word| !word.is_empty()).collect()
+}
+
+/// replaces dictionary words by 2-byte codewords, a prefix from `control_chars[1..]` selecting a block
+/// of 256 words and the index within that block.
+pub fn encode(input: &[u8], dictionary: &[u8], control_chars: &[u8]) -> Vec<u8> {
+    let prefixes = &control_chars[1..];
+    let ids: HashMap<&[u8], usize> = parse_dictionary(dictionary).into_iter().take(256*prefixes.len()).enumerate().map(|(k, word)| (word, k)).collect();
+
+    let mut out: Vec<u8> = Vec::with_capacity(input.len());
+    write_controls(&mut out, control_chars);
+
+    let mut n = 0;
+    while n < input.len() {
+        let len = input[n..].iter().take_while(|c| c.is_ascii_alphabetic()).count().max(1);
+        match ids.get(&input[n..n+len]) {
+            Some(&k) => out.extend([prefixes[k / 256], (k % 256) as u8]),
+            None => push_escaped(&mut out, &input[n..n+len], control_chars)
+        }
+        n += len;
+    }
+    out
+}
+
+pub fn decode(input: &[u8], dictionary: &[u8]) -> Vec<u8> {
+    let (control_chars, mut n) = read_controls(input);
+    let escape = control_chars[0];
+    let prefixes = &control_chars[1..];
+    let words = parse_dictionary(dictionary);
+
+    let mut out: Vec<u8> = Vec::with_capacity(2*input.len());
+    while n < input.len() {
+        let ch = input[n];
+        if ch == escape {
+            n += 1;
+            out.push(input[n]);
+        } else if let Some(block) = prefixes.iter().position(|&p| p == ch) {
+            n += 1;
+            out.extend( words[256*block + input[n] as usize] );
+        } else {
+            out.push(ch);
+        }
+        n += 1;
+    }
+    out
+}
+
+#[test]
+fn encode_decode() {
+    let sample = b"the quick brown fox jumps over the lazy dog, the fox and the dog jump over the fox".to_vec();
+    let dictionary = build_dictionary(&sample, 1000);
+    assert_eq!(dictionary, b"the\nover\nfox\ndog");
+
+    let control_chars = [b'~', 1u8];
+    let input = b"The fox jumps over the ~other~ dog\x01s".to_vec();
+    let encoded = encode(&input, &dictionary, &control_chars);
+    assert_eq!(&encoded[3..], b"The \x01\x02 jumps \x01\x01 \x01\x00 ~~other~~ \x01\x03~\x01s");
+    assert_eq!(decode(&encoded, &dictionary), input);
+}